@@ -0,0 +1,106 @@
+//! Compares the VM's fast path with the checked loop it replaces for
+//! verified chunks. Without arithmetic or calls the workloads are built from
+//! what the bytecode has: nested two-pass loops, string constants and
+//! negation. The VM is set up and the chunk verified once per workload, so
+//! only dispatch is timed.
+
+use std::io;
+
+use crafting_interpreters_rs::pipeline::{
+    bytecode::{Chunk, OpCode},
+    value::RTValue,
+    vm::VM,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Loops nested `depth` deep that each run their body twice, so the
+/// innermost `body` runs `2^depth` times. Each loop keeps a flag on the
+/// stack that starts truthy and is replaced with nil after the first pass.
+fn nested_loops(chunk: &mut Chunk, depth: usize, body: &dyn Fn(&mut Chunk)) {
+    if depth == 0 {
+        body(chunk);
+        return;
+    }
+    let truthy = chunk.push_constant(RTValue::number(1.0));
+    let nil = chunk.push_constant(RTValue::null());
+    chunk.push_load_constant_op(truthy, None);
+    let loop_start = chunk.code().len();
+    nested_loops(chunk, depth - 1, body);
+    let exit = chunk.push_jump(OpCode::JumpIfFalse, None);
+    chunk.push_op_code(OpCode::Pop, None);
+    chunk.push_load_constant_op(nil, None);
+    chunk.push_loop(loop_start, None);
+    chunk.patch_jump(exit);
+    chunk.push_op_code(OpCode::Pop, None);
+}
+
+fn workload(depth: usize, body: &dyn Fn(&mut Chunk)) -> Chunk {
+    let mut chunk = Chunk::new();
+    nested_loops(&mut chunk, depth, body);
+    chunk.push_constant_and_load_op(RTValue::null(), None);
+    chunk.push_op_code(OpCode::Return, None);
+    chunk.verify().expect("benchmark chunks verify");
+    chunk
+}
+
+fn loops() -> Chunk {
+    workload(16, &|_| {})
+}
+
+/// Stands in for the usual recursive fib benchmark until the VM has calls:
+/// like fib's call tree, the work doubles with each level, here by nesting
+/// loops 20 deep.
+fn deep_loops() -> Chunk {
+    workload(20, &|_| {})
+}
+
+fn strings() -> Chunk {
+    workload(12, &|chunk| {
+        for word in ["alpha", "beta", "gamma", "delta"] {
+            let constant = chunk.push_string_constant(word);
+            chunk.push_load_constant_op(constant, None);
+            chunk.push_op_code(OpCode::Pop, None);
+        }
+    })
+}
+
+fn negation() -> Chunk {
+    workload(12, &|chunk| {
+        let constant = chunk.push_constant(RTValue::number(2.5));
+        chunk.push_load_constant_op(constant, None);
+        for _ in 0..16 {
+            chunk.push_op_code(OpCode::Negate, None);
+        }
+        chunk.push_op_code(OpCode::Pop, None);
+    })
+}
+
+fn dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispatch");
+    for (name, chunk) in [
+        ("loops", loops()),
+        ("deep_loops", deep_loops()),
+        ("strings", strings()),
+        ("negation", negation()),
+    ] {
+        let mut vm = VM::new()
+            .with_output(Box::new(io::sink()))
+            .with_chunk(&chunk);
+        group.bench_function(BenchmarkId::new("checked", name), |b| {
+            b.iter(|| {
+                vm.reset();
+                vm.run_checked().unwrap()
+            })
+        });
+        group.bench_function(BenchmarkId::new("fast", name), |b| {
+            b.iter(|| {
+                vm.reset();
+                vm.run().unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
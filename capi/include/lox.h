@@ -0,0 +1,50 @@
+/* C API for the Lox VM. Build the library, liblox, with
+ * `cargo build --release -p lox-capi`. */
+
+#ifndef LOX_H
+#define LOX_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct LoxInterpreter LoxInterpreter;
+
+/* lox_run results. */
+#define LOX_OK 0
+#define LOX_LOAD_ERROR 1
+#define LOX_RUNTIME_ERROR 2
+
+/* lox_result_type results. */
+#define LOX_NIL 0
+#define LOX_NUMBER 1
+#define LOX_STRING 2
+
+/* An interpreter must only be used from one thread at a time. Strings it
+ * hands back stay valid until the next lox_run or lox_free. */
+LoxInterpreter *lox_new(void);
+void lox_free(LoxInterpreter *lox);
+
+/* Runs a compiled chunk or an assembler listing. source_name may be NULL. */
+int lox_run(LoxInterpreter *lox, const unsigned char *program, size_t len,
+            const char *source_name);
+
+/* The value the last successful run returned. */
+int lox_result_type(const LoxInterpreter *lox);
+/* NaN if the result isn't a number. */
+double lox_result_number(const LoxInterpreter *lox);
+/* NULL if the result isn't a string. len may be NULL. */
+const char *lox_result_string(const LoxInterpreter *lox, size_t *len);
+
+/* NULL if the last run succeeded. */
+const char *lox_error_message(const LoxInterpreter *lox);
+/* 0 if unknown. */
+size_t lox_error_line(const LoxInterpreter *lox);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
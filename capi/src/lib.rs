@@ -0,0 +1,4 @@
+//! The shared library for the C API in `crafting_interpreters_rs::capi`.
+//! It lives in its own crate so only builds that ask for it link a cdylib.
+
+pub use crafting_interpreters_rs::capi::*;
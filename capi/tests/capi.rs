@@ -0,0 +1,47 @@
+//! Builds `tests/capi_test.c` against the shared library and runs it.
+
+use std::{env, path::PathBuf, process::Command};
+
+#[test]
+fn c_program_uses_the_api() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let tmp = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+
+    // Build the library into a directory of its own, so the program links
+    // the one built from this tree and not whatever is lying in `deps`.
+    let target_dir = tmp.join("capi");
+    let mut cargo = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    cargo
+        .args(["build", "--quiet", "--manifest-path"])
+        .arg(manifest_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir);
+    if cfg!(feature = "nan-boxing") {
+        cargo.args(["--features", "nan-boxing"]);
+    }
+    let status = cargo.status().expect("failed to start cargo");
+    assert!(status.success(), "building the shared library failed");
+    let lib_dir = target_dir.join("debug");
+
+    let program = tmp.join("capi_test");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(compiler)
+        .arg(manifest_dir.join("tests/capi_test.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .args(["-llox", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .expect("failed to start the C compiler");
+    assert!(status.success(), "compiling the C test failed");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
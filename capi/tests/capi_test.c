@@ -0,0 +1,53 @@
+/* Exercises the C API. Prints what failed and exits nonzero on failure. */
+
+#include <math.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "lox.h"
+
+static int failures = 0;
+
+#define CHECK(condition)                                                      \
+    do {                                                                      \
+        if (!(condition)) {                                                   \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #condition);  \
+            failures++;                                                       \
+        }                                                                     \
+    } while (0)
+
+static int run(LoxInterpreter *lox, const char *listing) {
+    return lox_run(lox, (const unsigned char *)listing, strlen(listing), "test");
+}
+
+int main(void) {
+    LoxInterpreter *lox = lox_new();
+    size_t len = 0;
+
+    CHECK(run(lox, "Constant 1.5\nNegate\nReturn") == LOX_OK);
+    CHECK(lox_result_type(lox) == LOX_NUMBER);
+    CHECK(lox_result_number(lox) == -1.5);
+    CHECK(lox_result_string(lox, &len) == NULL);
+    CHECK(lox_error_message(lox) == NULL);
+
+    CHECK(run(lox, "Constant \"hello\"\nReturn") == LOX_OK);
+    CHECK(lox_result_type(lox) == LOX_STRING);
+    CHECK(strcmp(lox_result_string(lox, &len), "hello") == 0);
+    CHECK(len == 5);
+    CHECK(isnan(lox_result_number(lox)));
+
+    CHECK(run(lox, "") == LOX_OK);
+    CHECK(lox_result_type(lox) == LOX_NIL);
+
+    CHECK(run(lox, "Constant 1\nBogus") == LOX_LOAD_ERROR);
+    CHECK(lox_error_message(lox) != NULL);
+    CHECK(lox_error_line(lox) == 2);
+
+    CHECK(run(lox, "; line 7\nConstant nil\nNegate") == LOX_RUNTIME_ERROR);
+    CHECK(strcmp(lox_error_message(lox), "Operand must be a number.") == 0);
+    CHECK(lox_error_line(lox) == 7);
+
+    lox_free(lox);
+    lox_free(NULL);
+    return failures == 0 ? 0 : 1;
+}
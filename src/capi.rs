@@ -0,0 +1,212 @@
+//! The C API declared in `capi/include/lox.h`. Build the shared library with
+//! `cargo build --release -p lox-capi`.
+//!
+//! Every function takes the interpreter as a pointer from `lox_new`, which
+//! must only be used from one thread at a time. Strings handed back stay
+//! valid until the next call to `lox_run` or `lox_free` on the same
+//! interpreter.
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{
+    interpreter::Interpreter,
+    pipeline::value::{RTValue, Unpacked},
+};
+
+pub const LOX_OK: c_int = 0;
+pub const LOX_LOAD_ERROR: c_int = 1;
+pub const LOX_RUNTIME_ERROR: c_int = 2;
+
+pub const LOX_NIL: c_int = 0;
+pub const LOX_NUMBER: c_int = 1;
+pub const LOX_STRING: c_int = 2;
+
+pub struct LoxInterpreter {
+    interpreter: Interpreter,
+    result: RTValue,
+    /// The result's text, if it is a string.
+    result_string: Option<Vec<u8>>,
+    error: Option<CString>,
+    error_line: usize,
+}
+
+impl LoxInterpreter {
+    fn run(&mut self, program: &[u8], source_name: &str) -> c_int {
+        let chunk = match self.interpreter.load(program, source_name) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                self.fail(err.to_string(), err.line());
+                return LOX_LOAD_ERROR;
+            }
+        };
+        match self.interpreter.run(&chunk) {
+            Ok(value) => {
+                self.result = value;
+                self.result_string = match value.unpack() {
+                    Unpacked::String(symbol) => {
+                        self.interpreter.interner().resolve(symbol).map(|string| {
+                            let mut bytes = string.as_bytes().to_vec();
+                            bytes.push(0);
+                            bytes
+                        })
+                    }
+                    _ => None,
+                };
+                LOX_OK
+            }
+            Err(err) => {
+                self.fail(
+                    err.error().to_string(),
+                    err.line_info().map(|line_info| line_info.line),
+                );
+                LOX_RUNTIME_ERROR
+            }
+        }
+    }
+
+    fn fail(&mut self, message: String, line: Option<usize>) {
+        let message = message.replace('\0', "\\0");
+        self.error = Some(CString::new(message).expect("NULs were escaped"));
+        self.error_line = line.unwrap_or(0);
+    }
+
+    fn clear(&mut self) {
+        self.result = RTValue::null();
+        self.result_string = None;
+        self.error = None;
+        self.error_line = 0;
+    }
+}
+
+/// Creates an interpreter, to be freed with `lox_free`.
+#[no_mangle]
+pub extern "C" fn lox_new() -> *mut LoxInterpreter {
+    Box::into_raw(Box::new(LoxInterpreter {
+        interpreter: Interpreter::new(),
+        result: RTValue::null(),
+        result_string: None,
+        error: None,
+        error_line: 0,
+    }))
+}
+
+/// # Safety
+///
+/// `lox` must come from `lox_new` and not have been freed, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn lox_free(lox: *mut LoxInterpreter) {
+    if !lox.is_null() {
+        drop(unsafe { Box::from_raw(lox) });
+    }
+}
+
+/// Runs `len` bytes of `program`, a compiled chunk or an assembler listing,
+/// and returns `LOX_OK` or the kind of error. `source_name` may be null.
+///
+/// # Safety
+///
+/// `lox` must come from `lox_new`, `program` must point to `len` readable
+/// bytes, and `source_name` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lox_run(
+    lox: *mut LoxInterpreter,
+    program: *const u8,
+    len: usize,
+    source_name: *const c_char,
+) -> c_int {
+    let Some(lox) = (unsafe { lox.as_mut() }) else {
+        return LOX_LOAD_ERROR;
+    };
+    lox.clear();
+    let program = if program.is_null() {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(program, len) }
+    };
+    let source_name = if source_name.is_null() {
+        "<c>".into()
+    } else {
+        unsafe { CStr::from_ptr(source_name) }.to_string_lossy()
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| lox.run(program, &source_name))) {
+        Ok(status) => status,
+        Err(_) => {
+            lox.fail("Internal error".to_string(), None);
+            LOX_RUNTIME_ERROR
+        }
+    }
+}
+
+/// The type of the value the last successful run returned.
+///
+/// # Safety
+///
+/// `lox` must come from `lox_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_result_type(lox: *const LoxInterpreter) -> c_int {
+    match unsafe { lox.as_ref() }.map(|lox| lox.result.unpack()) {
+        Some(Unpacked::Number(_)) => LOX_NUMBER,
+        Some(Unpacked::String(_)) => LOX_STRING,
+        Some(Unpacked::Null) | None => LOX_NIL,
+    }
+}
+
+/// The result as a number, or NaN if it isn't one.
+///
+/// # Safety
+///
+/// `lox` must come from `lox_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_result_number(lox: *const LoxInterpreter) -> f64 {
+    unsafe { lox.as_ref() }
+        .and_then(|lox| lox.result.as_number())
+        .unwrap_or(f64::NAN)
+}
+
+/// The result as a NUL-terminated string, or null if it isn't one. Its
+/// length without the NUL is stored in `len` unless `len` is null; Lox
+/// strings may contain NULs.
+///
+/// # Safety
+///
+/// `lox` must come from `lox_new` and `len` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn lox_result_string(
+    lox: *const LoxInterpreter,
+    len: *mut usize,
+) -> *const c_char {
+    let Some(bytes) = unsafe { lox.as_ref() }.and_then(|lox| lox.result_string.as_ref()) else {
+        return ptr::null();
+    };
+    if !len.is_null() {
+        unsafe { *len = bytes.len() - 1 };
+    }
+    bytes.as_ptr().cast()
+}
+
+/// The message of the last run's error, or null if it succeeded.
+///
+/// # Safety
+///
+/// `lox` must come from `lox_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_error_message(lox: *const LoxInterpreter) -> *const c_char {
+    unsafe { lox.as_ref() }
+        .and_then(|lox| lox.error.as_ref())
+        .map_or(ptr::null(), |message| message.as_ptr())
+}
+
+/// The line of the last run's error, or 0 if unknown. Load errors report
+/// the listing line, runtime errors the source line.
+///
+/// # Safety
+///
+/// `lox` must come from `lox_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_error_line(lox: *const LoxInterpreter) -> usize {
+    unsafe { lox.as_ref() }.map_or(0, |lox| lox.error_line)
+}
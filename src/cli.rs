@@ -1,48 +1,414 @@
-use std::{env, io};
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+    process::ExitCode,
+    time::Duration,
+};
 
-use crate::pipeline::{
-    self,
-    bytecode::{Chunk, LineInfo, OpCode},
-    scanner,
-    value::RTValue, vm::VM,
+use crate::{
+    formatter,
+    interpreter::{self, Interpreter},
+    pipeline::{
+        self,
+        bytecode::{Chunk, Disassembler, LineInfo, OpCode},
+        value::RTValue,
+        vm::VM,
+    },
+    repl::{Editor, Lines, Repl},
 };
 
-pub struct CliConfig {}
+pub const USAGE: &str = "\
+usage: crafting-interpreters-rs [command]
+
+commands:
+    repl --listings           read and run assembler listings interactively
+    fmt [--check] [file...]   format Lox files in place, or stdin to stdout
+                              --check: only report files that need formatting
+    run [option...] file...   run .loxc chunks or assembler listings
+                              -O: optimize the bytecode first
+                              --fuel <n>: stop after n instructions
+                              --timeout <ms>: stop after ms milliseconds
+    disasm [option...] file...
+                              disassemble .loxc chunks or assembler listings
+                              -O: optimize the bytecode first
+                              --json: one JSON object per chunk
+                              --color=auto|always|never: ANSI colors
+                              --source <file>: show lines of this source;
+                                  also names the source of listings";
+
+pub struct CliConfig {
+    command: Command,
+}
+
+pub enum Command {
+    Demo,
+    Repl,
+    Fmt {
+        check: bool,
+        paths: Vec<String>,
+    },
+    Run {
+        optimize: bool,
+        limits: Limits,
+        paths: Vec<String>,
+    },
+    Disasm {
+        optimize: bool,
+        json: bool,
+        color: ColorChoice,
+        source: Option<String>,
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal, unless `NO_COLOR` is set.
+    Auto,
+    Always,
+    Never,
+}
+
+/// Bounds on how long a program may run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub fuel: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+impl Limits {
+    fn apply<'c>(&self, mut vm: VM<'c>) -> VM<'c> {
+        if let Some(fuel) = self.fuel {
+            vm = vm.with_fuel(fuel);
+        }
+        if let Some(timeout) = self.timeout {
+            vm = vm.with_timeout(timeout);
+        }
+        vm
+    }
+}
 
 impl CliConfig {
     #[allow(clippy::new_without_default)]
     pub fn new() -> CliConfig {
-        Self {}
+        Self {
+            command: Command::Demo,
+        }
+    }
+
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<CliConfig, String> {
+        let mut args = args.into_iter();
+        let command = match args.next().as_deref() {
+            None => Command::Demo,
+            Some("repl") => {
+                let mut listings = false;
+                for arg in args {
+                    match arg.as_str() {
+                        "--listings" => listings = true,
+                        _ => return Err(format!("Unexpected repl argument {}", arg)),
+                    }
+                }
+                if !listings {
+                    // Say so rather than treat Lox source as a listing.
+                    return Err(
+                        "repl can't read Lox until there is a compiler; repl --listings reads \
+                         assembler listings"
+                            .to_string(),
+                    );
+                }
+                Command::Repl
+            }
+            Some("fmt") => {
+                let mut check = false;
+                let mut paths = vec![];
+                for arg in args {
+                    match arg.as_str() {
+                        "--check" => check = true,
+                        flag if flag.starts_with('-') => {
+                            return Err(format!("Unknown fmt option {}", flag));
+                        }
+                        _ => paths.push(arg),
+                    }
+                }
+                Command::Fmt { check, paths }
+            }
+            Some("run") => {
+                let mut optimize = false;
+                let mut limits = Limits::default();
+                let mut paths = vec![];
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "-O" => optimize = true,
+                        "--fuel" => limits.fuel = Some(number_arg("--fuel", args.next())?),
+                        "--timeout" => {
+                            limits.timeout =
+                                Some(Duration::from_millis(number_arg("--timeout", args.next())?))
+                        }
+                        flag if flag.starts_with('-') => {
+                            return Err(format!("Unknown run option {}", flag));
+                        }
+                        _ => paths.push(arg),
+                    }
+                }
+                if paths.is_empty() {
+                    return Err("run needs at least one file".to_string());
+                }
+                Command::Run {
+                    optimize,
+                    limits,
+                    paths,
+                }
+            }
+            Some("disasm") => {
+                let mut optimize = false;
+                let mut json = false;
+                let mut color = ColorChoice::Auto;
+                let mut source = None;
+                let mut paths = vec![];
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "-O" => optimize = true,
+                        "--json" => json = true,
+                        "--color=auto" => color = ColorChoice::Auto,
+                        "--color=always" => color = ColorChoice::Always,
+                        "--color=never" => color = ColorChoice::Never,
+                        "--source" => match args.next() {
+                            Some(path) => source = Some(path),
+                            None => return Err("--source needs a file".to_string()),
+                        },
+                        flag if flag.starts_with('-') => {
+                            return Err(format!("Unknown disasm option {}", flag));
+                        }
+                        _ => paths.push(arg),
+                    }
+                }
+                if paths.is_empty() {
+                    return Err("disasm needs at least one file".to_string());
+                }
+                Command::Disasm {
+                    optimize,
+                    json,
+                    color,
+                    source,
+                    paths,
+                }
+            }
+            Some(other) => return Err(format!("Unknown command {}", other)),
+        };
+        Ok(Self { command })
     }
-}
 
-fn interpret(source: pipeline::source::Source) {
-    let tokens = scanner::scan(source);
-    println!("{:?}", tokens);
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
 }
 
-pub fn run(_config: &CliConfig) {
-    // if let Some(path) = env::args().nth(1) {
-    //     interpret(pipeline::source::from_file(&path).expect("Failed to open file"));
-    // }
+fn number_arg(flag: &str, arg: Option<String>) -> Result<u64, String> {
+    arg.and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| format!("{} needs a number", flag))
+}
 
-    // loop {
-    //     let input: String = io::stdin()
-    //         .lines()
-    //         .map_while(|line| line.ok().filter(|line| !line.is_empty()))
-    //         .intersperse("\n".to_string())
-    //         .collect();
-    //     if input.is_empty() {
-    //         break;
-    //     }
-    //     interpret(pipeline::source::from_repl_input(&input));
-    // }
+pub fn run(config: &CliConfig) -> ExitCode {
+    match config.command() {
+        Command::Demo => {
+            run_demo();
+            ExitCode::SUCCESS
+        }
+        Command::Repl => match run_repl() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Failed to read input: {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Fmt { check, paths } => run_fmt(*check, paths),
+        Command::Run {
+            optimize,
+            limits,
+            paths,
+        } => run_chunks(*optimize, *limits, paths),
+        Command::Disasm {
+            optimize,
+            json,
+            color,
+            source,
+            paths,
+        } => run_disasm(*optimize, *json, *color, source.as_deref(), paths),
+    }
+}
 
-    let mut vm = VM::new();
-    let source = "source";
+fn run_demo() {
+    let vm = VM::new();
     let mut chunk = Chunk::new();
-    chunk.push_constant_and_load_op(RTValue::Number(1.2), Some(LineInfo::new(source, 123, 0)));
+    let source = chunk.add_source("source");
+    chunk.push_constant_and_load_op(RTValue::number(1.2), Some(LineInfo::new(source, 123, 0)));
     chunk.push_op_code(OpCode::Return, Some(LineInfo::new(source, 123, 1)));
     chunk.describe_to_stderr(Some("test chunk"));
     vm.with_chunk(&chunk).run().unwrap();
 }
+
+/// Edits lines in a terminal, and reads plain lines otherwise.
+fn run_repl() -> io::Result<()> {
+    let mut repl = Repl::new();
+    if io::stdin().is_terminal() {
+        if let Ok(mut editor) = Editor::new(repl.interner()) {
+            return repl.run(&mut editor, io::stdout());
+        }
+    }
+    repl.run(&mut Lines(io::stdin().lock()), io::stdout())
+}
+
+fn run_fmt(check: bool, paths: &[String]) -> ExitCode {
+    if paths.is_empty() {
+        let mut text = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut text) {
+            eprintln!("Failed to read stdin: {}", err);
+            return ExitCode::FAILURE;
+        }
+        let source = pipeline::source::Source::new("<stdin>".to_string(), text.clone());
+        return match format_source(source) {
+            None => ExitCode::FAILURE,
+            Some(formatted) if check => {
+                if formatted == text {
+                    ExitCode::SUCCESS
+                } else {
+                    eprintln!("<stdin> needs formatting");
+                    ExitCode::FAILURE
+                }
+            }
+            Some(formatted) => {
+                io::stdout().write_all(formatted.as_bytes()).unwrap();
+                ExitCode::SUCCESS
+            }
+        };
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in paths {
+        let source = match pipeline::source::from_file(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", path, err);
+                exit_code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        let text = source.text().to_string();
+        let formatted = match format_source(source) {
+            Some(formatted) => formatted,
+            None => {
+                exit_code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        if formatted == text {
+            continue;
+        }
+        if check {
+            eprintln!("{} needs formatting", path);
+            exit_code = ExitCode::FAILURE;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("Failed to write {}: {}", path, err);
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+    exit_code
+}
+
+/// Loads a compiled chunk, or assembles anything that isn't one as a
+/// listing of the source called `source_name`.
+fn load_chunk(path: &str, source_name: &str) -> Result<Chunk, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Failed to read: {}", err))?;
+    Interpreter::new()
+        .load(&bytes, source_name)
+        .map_err(|err| err.to_string())
+}
+
+fn run_chunks(optimize: bool, limits: Limits, paths: &[String]) -> ExitCode {
+    for path in paths {
+        let mut chunk = match load_chunk(path, path) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                return ExitCode::FAILURE;
+            }
+        };
+        if optimize {
+            chunk.optimize();
+        }
+        let mut vm = limits.apply(VM::new()).with_chunk(&chunk);
+        if let Err(err) = vm.run() {
+            eprintln!("{}: {}", path, interpreter::Error::at(err, &vm, &chunk));
+            return ExitCode::from(70);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn run_disasm(
+    optimize: bool,
+    json: bool,
+    color: ColorChoice,
+    source: Option<&str>,
+    paths: &[String],
+) -> ExitCode {
+    let color = match color {
+        ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    let source = match source.map(pipeline::source::from_file).transpose() {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", source.unwrap(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut out = io::stdout().lock();
+    for path in paths {
+        let source_name = source
+            .as_ref()
+            .map_or(path.as_str(), |source| source.name());
+        let mut chunk = match load_chunk(path, source_name) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                exit_code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        if optimize {
+            chunk.optimize();
+        }
+
+        let mut disassembler = Disassembler::new(&chunk).with_name(path).with_color(color);
+        if let Some(source) = &source {
+            disassembler = disassembler.with_source(source);
+        }
+        let written = if json {
+            disassembler.write_json(&mut out)
+        } else {
+            disassembler.write_text(&mut out)
+        };
+        if let Err(err) = written {
+            eprintln!("Failed to write disassembly: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+    exit_code
+}
+
+fn format_source(source: pipeline::source::Source) -> Option<String> {
+    let name = source.name().to_string();
+    match formatter::format(source) {
+        Ok(formatted) => Some(formatted),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: {}", name, error.message());
+            }
+            None
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::pipeline::{
+    interner::Interner,
+    scanner::{self, ScanError},
+    source::Source,
+    tokens::{LosslessToken, TokenInfo, TriviaKind},
+};
+
+pub const INDENT_WIDTH: usize = 2;
+pub const MAX_WIDTH: usize = 80;
+// Wrapped lines are indented two extra levels past their statement.
+const CONTINUATION_INDENT: usize = 2;
+
+/// Pretty-prints Lox source text in a canonical layout. The formatter works on
+/// the lossless token stream, so every comment in the source is preserved, and
+/// formatting already formatted text gives back the same text.
+///
+/// Sources with scan errors are not formatted, since we can't tell what the
+/// unscannable text was meant to be.
+pub fn format(source: Source) -> Result<String, Vec<ScanError>> {
+    let (errors, tokens) = scanner::scan_lossless(source, &Interner::new());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut formatter = Formatter::new();
+    for token in &tokens {
+        formatter.push_token(token);
+    }
+    Ok(formatter.finish())
+}
+
+struct Formatter<'t> {
+    out: String,
+    line_len: usize,
+    depth: usize,
+    paren_depth: usize,
+    mid_statement: bool,
+    previous: Option<(&'t TokenInfo, bool)>,
+}
+
+impl<'t> Formatter<'t> {
+    fn new() -> Self {
+        Self {
+            out: String::new(),
+            line_len: 0,
+            depth: 0,
+            paren_depth: 0,
+            mid_statement: false,
+            previous: None,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if self.line_len > 0 {
+            self.newline();
+        }
+        self.out
+    }
+
+    fn push_token(&mut self, token: &'t LosslessToken) {
+        let info = token.token().info();
+        if let Some((previous, _)) = self.previous {
+            if breaks_line(previous, info, self.paren_depth) {
+                self.mid_statement = false;
+                if self.line_len > 0 {
+                    self.newline();
+                }
+            }
+        }
+        let mut newlines = 0;
+        for trivia in token.leading() {
+            match trivia.kind() {
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::LineComment => {
+                    if self.line_len > 0 {
+                        self.newline();
+                    }
+                    self.blank_line_if(newlines >= 2);
+                    self.write(trivia.text(), false);
+                    self.newline();
+                    newlines = 0;
+                }
+                TriviaKind::Whitespace | TriviaKind::Skipped => {}
+            }
+        }
+        if *info == TokenInfo::EOF {
+            return;
+        }
+        // Comments before a closing brace stay indented with the block.
+        if *info == TokenInfo::RightBrace {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        self.blank_line_if(
+            newlines >= 2
+                && self.line_len == 0
+                && !self.mid_statement
+                && *info != TokenInfo::RightBrace,
+        );
+
+        let unary = is_unary(self.previous.map(|(previous, _)| previous), info);
+        let space = match self.previous {
+            Some((previous, previous_unary)) => spaced(previous, previous_unary, info),
+            None => false,
+        };
+        let [first, rest @ ..] = token.token().lexeme_parts();
+        if space
+            && self.line_len > 0
+            && self.line_len + 1 + first_line_len(&[first, rest[0], rest[1]]) > MAX_WIDTH
+        {
+            self.newline();
+        }
+        self.write(first, space);
+        for part in rest {
+            self.write(part, false);
+        }
+        self.mid_statement = true;
+        self.previous = Some((info, unary));
+        match info {
+            // A block starts a new statement, even if it only holds comments.
+            TokenInfo::LeftBrace => {
+                self.depth += 1;
+                self.mid_statement = false;
+            }
+            TokenInfo::LeftParen => self.paren_depth += 1,
+            TokenInfo::RightParen => self.paren_depth = self.paren_depth.saturating_sub(1),
+            _ => {}
+        }
+
+        for trivia in token.trailing() {
+            if *trivia.kind() == TriviaKind::LineComment {
+                self.write(trivia.text(), true);
+                self.newline();
+            }
+        }
+    }
+
+    fn write(&mut self, text: &str, space: bool) {
+        if self.line_len == 0 {
+            let levels = self.depth
+                + if self.mid_statement {
+                    CONTINUATION_INDENT
+                } else {
+                    0
+                };
+            self.out.push_str(&" ".repeat(levels * INDENT_WIDTH));
+            self.line_len = levels * INDENT_WIDTH;
+        } else if space {
+            self.out.push(' ');
+            self.line_len += 1;
+        }
+        self.out.push_str(text);
+        match text.rfind('\n') {
+            Some(index) => self.line_len = text[index + 1..].chars().count(),
+            None => self.line_len += text.chars().count(),
+        }
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.line_len = 0;
+    }
+
+    // Keeps at most one blank line, and never at the start of the output or
+    // right after an opening brace.
+    fn blank_line_if(&mut self, wanted: bool) {
+        if wanted
+            && !self.out.is_empty()
+            && !self.out.ends_with("{\n")
+            && !self.out.ends_with("\n\n")
+        {
+            self.newline();
+        }
+    }
+}
+
+fn breaks_line(previous: &TokenInfo, next: &TokenInfo, paren_depth: usize) -> bool {
+    match (previous, next) {
+        (TokenInfo::LeftBrace, TokenInfo::RightBrace) => false,
+        (TokenInfo::LeftBrace, _) | (_, TokenInfo::RightBrace) => true,
+        (TokenInfo::Semicolon, _) => paren_depth == 0,
+        (TokenInfo::RightBrace, next) => !matches!(
+            next,
+            TokenInfo::Else
+                | TokenInfo::Semicolon
+                | TokenInfo::RightParen
+                | TokenInfo::Comma
+                | TokenInfo::Dot
+        ),
+        _ => false,
+    }
+}
+
+fn spaced(previous: &TokenInfo, previous_unary: bool, next: &TokenInfo) -> bool {
+    match (previous, next) {
+        (TokenInfo::LeftBrace, TokenInfo::RightBrace) => false,
+        (_, TokenInfo::RightParen | TokenInfo::Comma | TokenInfo::Semicolon | TokenInfo::Dot) => {
+            false
+        }
+        (TokenInfo::LeftParen | TokenInfo::Dot, _) => false,
+        (TokenInfo::Bang, _) => false,
+        (TokenInfo::Minus, _) if previous_unary => false,
+        (previous, TokenInfo::LeftParen) => !ends_operand(previous),
+        _ => true,
+    }
+}
+
+fn is_unary(previous: Option<&TokenInfo>, info: &TokenInfo) -> bool {
+    match info {
+        TokenInfo::Bang => true,
+        TokenInfo::Minus => !previous.is_some_and(ends_operand),
+        _ => false,
+    }
+}
+
+fn ends_operand(info: &TokenInfo) -> bool {
+    matches!(
+        info,
+        TokenInfo::Identifier(_)
+            | TokenInfo::StringLiteral(_)
+            | TokenInfo::NumberLiteral(_)
+            | TokenInfo::RightParen
+            | TokenInfo::True
+            | TokenInfo::False
+            | TokenInfo::Nil
+            | TokenInfo::This
+            | TokenInfo::Super
+    )
+}
+
+/// The width of the first line of the text `parts` make up.
+fn first_line_len(parts: &[&str]) -> usize {
+    let mut len = 0;
+    for part in parts {
+        match part.split_once('\n') {
+            Some((line, _)) => return len + line.chars().count(),
+            None => len += part.chars().count(),
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::source;
+
+    use super::format;
+
+    fn fmt(text: &str) -> String {
+        format(source::from_repl_input(text)).unwrap()
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            fmt("var  x=1+-2*(3 -4);fun f(a,b){return !a and b;}\nclass A<B{init(){this.x=f(1)(2);}}\
+                 if(x>=1){x=x-1;}else{}for(var i=0;i<10;i=i+1)f(i);"),
+            "var x = 1 + -2 * (3 - 4);\n\
+             fun f(a, b) {\n  return !a and b;\n}\n\
+             class A < B {\n  init() {\n    this.x = f(1)(2);\n  }\n}\n\
+             if (x >= 1) {\n  x = x - 1;\n} else {}\n\
+             for (var i = 0; i < 10; i = i + 1) f(i);\n"
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        assert_eq!(
+            fmt("\n\n// header\n\n\nvar a;   // trailing\n\n\n\nfun f() {\n\n  // inside\n  return a +   // why\n b;\n\n}\n// end"),
+            "// header\n\n\
+             var a; // trailing\n\n\
+             fun f() {\n  // inside\n  return a + // why\n      b;\n}\n\
+             // end\n"
+        );
+    }
+
+    #[test]
+    fn blocks_holding_only_comments() {
+        assert_eq!(fmt("while (x) {\n // c\n}"), "while (x) {\n  // c\n}\n");
+        assert_eq!(fmt("{ // c\n}"), "{ // c\n}\n");
+        assert_eq!(fmt("{ a;\n// c\n}"), "{\n  a;\n  // c\n}\n");
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        assert_eq!(
+            fmt("{ var total = first_long_argument_name + second_long_argument_name + third_long_name; }"),
+            "{\n  var total = first_long_argument_name + second_long_argument_name +\n      third_long_name;\n}\n"
+        );
+    }
+
+    #[test]
+    fn refuses_unscannable_source() {
+        let errors = format(source::from_repl_input("var a = @;")).unwrap_err();
+        assert_eq!(errors[0].message(), "Unexpected character @");
+    }
+
+    #[test]
+    fn idempotent() {
+        for text in [
+            "var  x=1+-2*(3 -4);fun f(a,b){return !a and b;}",
+            "class A<B{init(){this.x=f(1)(2);}}\n\n\n// trailing comment\nvar s = \"multi\nline\";",
+            "fun f() {\n\n  // inside\n  return a +   // why\n b;\n\n}\n// end",
+            "while (true) { if (a) { b(); } else if (c) { d(); } else { e(); } }",
+            "{ var total = first_long_argument_name + second_long_argument_name + third_long_name + more; }",
+            "print(a ? b : c, -x, !y, 0x1F, 1_000);",
+            "while (x) {\n // c\n}",
+        ] {
+            let once = fmt(text);
+            assert_eq!(fmt(&once), once);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! A host-facing entry point for running Lox bytecode from Rust and moving
+//! values across. It covers what the runtime supports today; the rest of
+//! the embedding API waits on what it needs:
+//!
+//! - Evaluating source text needs the parser and compiler.
+//! - Reading and setting globals needs global variables in the VM.
+//! - Calling functions and methods by name needs functions, classes and
+//!   calls.
+//! - Converting `bool`, `Vec` and `HashMap` needs booleans, lists and maps
+//!   among the runtime's values.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+};
+
+use crate::pipeline::{
+    bytecode::{file::ChunkFileError, file::MAGIC, AssembleError, Chunk, LineInfo},
+    interner::Interner,
+    value::{FromLox, FromLoxError, RTValue, ToLox, Unpacked},
+    vm::{InterpretError, VM},
+};
+
+/// Runs chunks that share one string table, so values can be converted to
+/// and from Rust types between runs.
+#[derive(Default)]
+pub struct Interpreter {
+    interner: Interner,
+}
+
+/// Why a chunk couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Chunk(ChunkFileError),
+    Listing(AssembleError),
+    NotUtf8,
+}
+
+impl LoadError {
+    /// The listing line the error is on, for listings.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            LoadError::Listing(err) => Some(err.line()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Chunk(err) => write!(f, "{}", err),
+            LoadError::Listing(err) => write!(f, "{}", err),
+            LoadError::NotUtf8 => write!(f, "Not a chunk file or UTF-8 listing"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A failed run, with where in the source it failed if the chunk knows.
+#[derive(Debug)]
+pub struct Error {
+    error: InterpretError,
+    line_info: Option<LineInfo>,
+}
+
+impl Error {
+    /// The error a run of `chunk` on `vm` failed with, at the line of the
+    /// instruction that failed.
+    pub fn at(error: InterpretError, vm: &VM, chunk: &Chunk) -> Self {
+        Self {
+            error,
+            line_info: vm
+                .error_offset()
+                .and_then(|offset| chunk.get_line_info(offset))
+                .copied(),
+        }
+    }
+
+    pub fn error(&self) -> &InterpretError {
+        &self.error
+    }
+
+    pub fn line_info(&self) -> Option<LineInfo> {
+        self.line_info
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.line_info {
+            Some(line_info) => write!(f, "[line {}] {}", line_info.line, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// An empty chunk whose strings go into this interpreter's table.
+    pub fn new_chunk(&self) -> Chunk {
+        Chunk::with_interner(self.interner.clone())
+    }
+
+    /// Loads a compiled chunk, or assembles anything that isn't one as a
+    /// listing of the source called `source_name`.
+    pub fn load(&self, bytes: &[u8], source_name: &str) -> Result<Chunk, LoadError> {
+        if bytes.starts_with(&MAGIC) {
+            Chunk::read_from(&mut &bytes[..], self.interner.clone()).map_err(LoadError::Chunk)
+        } else {
+            let listing = std::str::from_utf8(bytes).map_err(|_| LoadError::NotUtf8)?;
+            Chunk::assemble_with_interner(listing, source_name, self.interner.clone())
+                .map_err(LoadError::Listing)
+        }
+    }
+
+    /// Runs `chunk` and returns the value it returned, or nil if it ended
+    /// without returning. The returned value isn't printed.
+    pub fn run(&self, chunk: &Chunk) -> Result<RTValue, Error> {
+        if !chunk.interner().shares_table_with(&self.interner) {
+            return Err(Error {
+                error: InterpretError::RuntimeError(
+                    "Chunk strings come from another interpreter".to_string(),
+                ),
+                line_info: None,
+            });
+        }
+        let mut vm = VM::new()
+            .with_output(Box::new(io::sink()))
+            .with_chunk(chunk);
+        match vm.run() {
+            Ok(()) => Ok(vm.returned().unwrap_or(RTValue::null())),
+            Err(error) => Err(Error::at(error, &vm, chunk)),
+        }
+    }
+
+    /// `value` the way Lox prints it.
+    pub fn format(&self, value: RTValue) -> String {
+        match value.unpack() {
+            Unpacked::Null => "nil".to_string(),
+            Unpacked::Number(number) => number.to_string(),
+            Unpacked::String(symbol) => match self.interner.resolve(symbol) {
+                Some(string) => string.to_string(),
+                None => format!("<BAD SYMBOL {}>", symbol.index()),
+            },
+        }
+    }
+
+    pub fn to_value(&self, value: impl ToLox) -> RTValue {
+        value.to_lox(&self.interner)
+    }
+
+    pub fn from_value<T: FromLox>(&self, value: RTValue) -> Result<T, FromLoxError> {
+        T::from_lox(value, &self.interner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{bytecode::OpCode, vm::InterpretError};
+
+    use super::Interpreter;
+
+    #[test]
+    fn runs_chunks_and_converts_results() {
+        let interpreter = Interpreter::new();
+        let mut chunk = interpreter.new_chunk();
+        chunk.push_constant_and_load_op(interpreter.to_value("greeting"), None);
+        chunk.push_op_code(OpCode::Pop, None);
+        chunk.push_constant_and_load_op(interpreter.to_value(4), None);
+        chunk.push_op_code(OpCode::Negate, None);
+        chunk.push_op_code(OpCode::Return, None);
+        let result = interpreter.run(&chunk).unwrap();
+        assert_eq!(interpreter.from_value::<i32>(result), Ok(-4));
+        assert!(interpreter.from_value::<String>(result).is_err());
+
+        let chunk = interpreter
+            .load(b"Constant \"done\"\nReturn", "test")
+            .unwrap();
+        let result = interpreter.run(&chunk).unwrap();
+        assert_eq!(
+            interpreter.from_value::<String>(result).as_deref(),
+            Ok("done")
+        );
+
+        assert_eq!(interpreter.format(result), "done");
+        assert_eq!(interpreter.format(interpreter.to_value(-1.5)), "-1.5");
+        assert_eq!(interpreter.format(interpreter.to_value(3)), "3");
+
+        let empty = interpreter.run(&interpreter.new_chunk()).unwrap();
+        assert_eq!(interpreter.format(empty), "nil");
+        assert_eq!(interpreter.from_value::<Option<f64>>(empty), Ok(None));
+    }
+
+    #[test]
+    fn reports_where_errors_happen() {
+        let interpreter = Interpreter::new();
+        let Err(err) = interpreter.load(b"Constant 1\nBogus", "test") else {
+            panic!("Bogus assembled");
+        };
+        assert_eq!(err.line(), Some(2));
+
+        let chunk = interpreter
+            .load(b"; line 1\nConstant nil\n; line 3\nNegate", "test")
+            .unwrap();
+        let err = interpreter.run(&chunk).unwrap_err();
+        assert_eq!(err.to_string(), "[line 3] Operand must be a number.");
+
+        let chunk = Interpreter::new().new_chunk();
+        assert!(matches!(
+            interpreter.run(&chunk).unwrap_err().error(),
+            InterpretError::RuntimeError(_)
+        ));
+    }
+}
@@ -1,4 +1,9 @@
 #![feature(never_type)]
 #![feature(iter_intersperse)]
+#[cfg(feature = "capi")]
+pub mod capi;
 pub mod cli;
+pub mod formatter;
+pub mod interpreter;
 pub mod pipeline;
+pub mod repl;
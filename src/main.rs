@@ -1,5 +1,13 @@
+use std::{env, process::ExitCode};
+
 use crafting_interpreters_rs::cli::{self, CliConfig};
 
-fn main() {
-    cli::run(&CliConfig::new())
+fn main() -> ExitCode {
+    match CliConfig::from_args(env::args().skip(1)) {
+        Ok(config) => cli::run(&config),
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            ExitCode::from(64)
+        }
+    }
 }
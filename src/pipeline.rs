@@ -1,4 +1,5 @@
 pub mod source;
+pub mod interner;
 pub mod scanner;
 pub mod tokens;
 pub mod parser;
@@ -1,36 +1,70 @@
 mod opcode;
-use std::mem;
+pub use opcode::{DecodeError, Instruction, OpCode, OpInfo, Operand, OperandKind, MAX_OPCODE};
 
-pub use opcode::OpCode;
+mod constants;
+pub use constants::{ConstantKind, ConstantPool};
+
+mod instructions;
+pub use instructions::Instructions;
 
 mod source_map;
-pub use source_map::{LineInfo, SourceMap};
+pub use source_map::{LineInfo, SourceId, SourceMap};
 
 pub mod bytes;
-use bytes::ToBytes;
 
 pub mod debug;
 
-use super::value::RTValue;
+mod disassembler;
+pub use disassembler::Disassembler;
+
+pub mod file;
+
+mod assembler;
+pub use assembler::AssembleError;
+
+mod optimizer;
 
-pub struct Chunk<'s> {
-    constants: Vec<RTValue>,
+mod verify;
+pub use verify::{VerifyError, VerifyErrorKind};
+
+use super::{interner::Interner, value::RTValue};
+
+pub struct Chunk {
+    constants: ConstantPool,
     code: Vec<u8>,
-    source_map: source_map::SourceMap<'s>,
+    source_map: SourceMap,
+    interner: Interner,
 }
 
 pub type ConstantIndex = usize;
-pub const CONSTANT_LONG_ARG_BYTES: usize = mem::size_of::<ConstantIndex>();
-
-impl<'s> Chunk<'s> {
+/// `ConstantLong` takes a 24-bit big-endian operand on every platform, which
+/// bounds how many constants a chunk can address.
+pub const CONSTANT_LONG_ARG_BYTES: usize = 3;
+pub const MAX_CONSTANTS: usize = 1 << (8 * CONSTANT_LONG_ARG_BYTES);
+/// Jumps take a 16-bit big-endian distance, as in clox.
+pub const JUMP_ARG_BYTES: usize = 2;
+pub const MAX_JUMP: usize = (1 << (8 * JUMP_ARG_BYTES)) - 1;
+
+impl Chunk {
     pub fn new() -> Self {
+        Self::with_interner(Interner::new())
+    }
+
+    /// String constants in the chunk are symbols from this interner, which
+    /// should be the same one used to scan the chunk's source.
+    pub fn with_interner(interner: Interner) -> Self {
         Self {
-            constants: Vec::new(),
+            constants: ConstantPool::new(),
             code: Vec::new(),
             source_map: SourceMap::new(),
+            interner,
         }
     }
 
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
     pub fn code(&self) -> &[u8] {
         self.code.as_ref()
     }
@@ -40,11 +74,24 @@ impl<'s> Chunk<'s> {
         self.constants.get(constant_index)
     }
 
-    pub fn get_line_info(&self, instruction_index: usize) -> Option<&LineInfo<'s>> {
+    pub fn get_line_info(&self, instruction_index: usize) -> Option<&LineInfo> {
         self.source_map.get_line_info(instruction_index)
     }
 
-    pub fn push_op_code(&mut self, op: OpCode, line_info: Option<LineInfo<'s>>) {
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Returns the id to use in line info for the source called `name`.
+    pub fn add_source(&mut self, name: &str) -> SourceId {
+        self.source_map.add_file(name)
+    }
+
+    pub fn source_name(&self, source: SourceId) -> Option<&str> {
+        self.source_map.file_name(source)
+    }
+
+    pub fn push_op_code(&mut self, op: OpCode, line_info: Option<LineInfo>) {
         self.code.push(op as u8);
         if let Some(line_info) = line_info {
             self.source_map
@@ -52,7 +99,7 @@ impl<'s> Chunk<'s> {
         }
     }
 
-    pub fn push_op_arg(&mut self, arg: u8, line_info: Option<LineInfo<'s>>) {
+    pub fn push_op_arg(&mut self, arg: u8, line_info: Option<LineInfo>) {
         self.code.push(arg);
         if let Some(line_info) = line_info {
             self.source_map
@@ -60,37 +107,107 @@ impl<'s> Chunk<'s> {
         }
     }
 
+    pub fn constants(&self) -> &ConstantPool {
+        &self.constants
+    }
+
+    /// Adds a constant unless an equivalent one is already in the pool, and
+    /// returns its index either way.
     pub fn push_constant(&mut self, value: RTValue) -> ConstantIndex {
-        self.constants.push(value);
-        self.constants.len() - 1
+        self.constants.insert(value)
+    }
+
+    pub fn push_string_constant(&mut self, string: &str) -> ConstantIndex {
+        let symbol = self.interner.intern(string);
+        self.push_constant(RTValue::string(symbol))
     }
 
+    /// # Panics
+    ///
+    /// If `constant_index` is not below `MAX_CONSTANTS`.
     pub fn push_load_constant_op(
         &mut self,
         constant_index: ConstantIndex,
-        line_info: Option<LineInfo<'s>>,
+        line_info: Option<LineInfo>,
     ) {
-        match u8::try_from(constant_index) {
-            Ok(byte) => {
-                self.push_op_code(OpCode::Constant, line_info.clone());
-                self.push_op_arg(byte, line_info);
-            }
-            Err(_) => {
-                self.push_op_code(OpCode::ConstantLong, line_info.clone());
-                for byte in ToBytes::<CONSTANT_LONG_ARG_BYTES>::num_to_bytes(&constant_index) {
-                    self.push_op_arg(byte, line_info.clone());
-                }
-            }
-        }
+        let instruction = if constant_index <= u8::MAX as usize {
+            Instruction::Constant(constant_index)
+        } else {
+            assert!(
+                constant_index < MAX_CONSTANTS,
+                "Constant index {} does not fit in a ConstantLong operand",
+                constant_index
+            );
+            Instruction::ConstantLong(constant_index)
+        };
+        self.push_instruction(instruction, line_info);
     }
 
-    pub fn push_constant_and_load_op(&mut self, value: RTValue, line_info: Option<LineInfo<'s>>) {
+    pub fn push_constant_and_load_op(&mut self, value: RTValue, line_info: Option<LineInfo>) {
         let constant_index = self.push_constant(value);
         self.push_load_constant_op(constant_index, line_info);
     }
+
+    /// Pushes a forward jump with a placeholder distance and returns its
+    /// offset, to be passed to `patch_jump` once the target is known.
+    ///
+    /// # Panics
+    ///
+    /// If `op` is not a forward jump.
+    pub fn push_jump(&mut self, op: OpCode, line_info: Option<LineInfo>) -> usize {
+        let instruction = match op {
+            OpCode::Jump => Instruction::Jump(MAX_JUMP),
+            OpCode::JumpIfFalse => Instruction::JumpIfFalse(MAX_JUMP),
+            other => panic!("{:?} is not a forward jump", other),
+        };
+        let offset = self.code.len();
+        self.push_instruction(instruction, line_info);
+        offset
+    }
+
+    /// Makes the jump at `offset` land on the end of the code.
+    ///
+    /// # Panics
+    ///
+    /// If there is no forward jump at `offset`, or it would have to jump
+    /// further than `MAX_JUMP`.
+    pub fn patch_jump(&mut self, offset: usize) {
+        let instruction = match self.instruction_at(offset) {
+            Some(Ok(instruction @ (Instruction::Jump(_) | Instruction::JumpIfFalse(_)))) => {
+                instruction
+            }
+            other => panic!("No forward jump to patch at {}: {:?}", offset, other),
+        };
+        let distance = self.code.len() - (offset + instruction.length());
+        assert!(
+            distance <= MAX_JUMP,
+            "Jump distance {} does not fit in a jump operand",
+            distance
+        );
+        let mut code = vec![];
+        Instruction::new(instruction.op_code(), Some(distance))
+            .unwrap()
+            .encode(&mut code);
+        self.code[offset..offset + code.len()].copy_from_slice(&code);
+    }
+
+    /// Pushes a `Loop` back to `loop_start`.
+    ///
+    /// # Panics
+    ///
+    /// If `loop_start` is more than `MAX_JUMP` bytes back.
+    pub fn push_loop(&mut self, loop_start: usize, line_info: Option<LineInfo>) {
+        let distance = self.code.len() + 1 + JUMP_ARG_BYTES - loop_start;
+        assert!(
+            distance <= MAX_JUMP,
+            "Loop distance {} does not fit in a jump operand",
+            distance
+        );
+        self.push_instruction(Instruction::Loop(distance), line_info);
+    }
 }
 
-impl<'s> Default for Chunk<'s> {
+impl Default for Chunk {
     fn default() -> Self {
         Self::new()
     }
@@ -0,0 +1,712 @@
+//! Assembles textual listings into chunks. A listing has one instruction per
+//! line, either hand-written:
+//!
+//! ```text
+//! start:                 ; labels name the offset of the next instruction
+//! ; line 3               ; later instructions get line 3
+//! Constant 1.5           ; adds 1.5 to the constant pool
+//! JumpIfFalse end        ; jumps can go to labels before or after them
+//! ConstantLong "text"
+//! end:
+//! Return
+//! ```
+//!
+//! or in the format written by `Chunk::describe`, with offset, line, constant
+//! index and jump distance spelled out, and directives for what the
+//! instruction lines don't show: the file table, constants no instruction
+//! loads, and spans that don't start at column 0 of their line.
+//!
+//! ```text
+//! .file "main.lox"             ; line numbers refer to the first file
+//! .constant    1 Number(2.0)   ; unused constants are kept too
+//! .span 0 3:4-3:6              ; file index and start:column-end:column
+//! 0000    3 Constant    0 Number(1.5)
+//! 0002    | JumpIfFalse    0 -> 0005
+//! 0005    ? Return
+//! ```
+//!
+//! Re-assembling the description of a chunk gives back exactly that chunk.
+//! Listings without `.file` directives get line info in the source named by
+//! `Chunk::assemble`.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+};
+
+use crate::pipeline::{interner::Interner, value::RTValue};
+
+use super::{Chunk, Instruction, LineInfo, OpCode, OperandKind, SourceId, MAX_CONSTANTS, MAX_JUMP};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    line: usize,
+    message: String,
+}
+
+impl AssembleError {
+    /// Line of the listing where the error was found, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_ref()
+    }
+}
+
+impl Display for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+impl Chunk {
+    pub fn assemble(listing: &str, source_name: &str) -> Result<Chunk, AssembleError> {
+        Self::assemble_with_interner(listing, source_name, Interner::new())
+    }
+
+    /// Assembles a listing whose strings go into `interner`.
+    pub fn assemble_with_interner(
+        listing: &str,
+        source_name: &str,
+        interner: Interner,
+    ) -> Result<Chunk, AssembleError> {
+        let mut assembler = Assembler {
+            chunk: Chunk::with_interner(interner),
+            source_name: source_name.to_string(),
+            source: None,
+            line_info: None,
+            labels: HashMap::new(),
+            fixups: vec![],
+            constants: HashMap::new(),
+        };
+        for (index, text) in listing.lines().enumerate() {
+            assembler
+                .assemble_line(text, index + 1)
+                .map_err(|message| AssembleError {
+                    line: index + 1,
+                    message,
+                })?;
+        }
+        assembler.resolve_labels()?;
+        Ok(assembler.chunk)
+    }
+
+    /// The directives `describe` writes before the instructions, so that its
+    /// output assembles back into exactly this chunk: the file table, and the
+    /// constants no instruction loads, which the instructions can't bring
+    /// back.
+    pub(super) fn write_preamble<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for file in self.source_map.files() {
+            writeln!(w, ".file {:?}", file)?;
+        }
+        let mut loaded = vec![false; self.constants.len()];
+        for (_, decoded) in self.instructions() {
+            let Ok(instruction) = decoded else { continue };
+            let loads_constant = matches!(
+                instruction.info().operand,
+                Some(ref operand) if operand.kind == OperandKind::Constant
+            );
+            if let Some(slot) = instruction
+                .operand()
+                .filter(|_| loads_constant)
+                .and_then(|index| loaded.get_mut(index))
+            {
+                *slot = true;
+            }
+        }
+        for (index, value) in self.constants.iter().enumerate() {
+            if !loaded[index] {
+                writeln!(w, ".constant {:>4} {}", index, self.describe_value(value))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The `.span` directive the instruction at `offset` needs, if the
+    /// assembler can't tell its line info from the line column. `previous`
+    /// is the line info of the instruction before it.
+    ///
+    /// An instruction on the line the previous one started on keeps its
+    /// span; one on a new line gets column 0 of that line in the first file.
+    pub(super) fn span_directive(
+        &self,
+        offset: usize,
+        previous: Option<LineInfo>,
+    ) -> Option<String> {
+        let info = *self.get_line_info(offset)?;
+        let read_back = match previous {
+            Some(previous) if previous.line == info.line => previous,
+            _ => LineInfo::new(self.source_map.file_id(0)?, info.line, 0),
+        };
+        (read_back != info).then(|| {
+            format!(
+                ".span {} {}:{}-{}:{}",
+                info.source.index(),
+                info.line,
+                info.column,
+                info.end_line,
+                info.end_column
+            )
+        })
+    }
+}
+
+struct Assembler {
+    chunk: Chunk,
+    source_name: String,
+    // The first file the listing names, or else the source named by
+    // `assemble`, added to the file table when a line first refers to it.
+    source: Option<SourceId>,
+    line_info: Option<LineInfo>,
+    labels: HashMap<String, usize>,
+    fixups: Vec<Fixup>,
+    // The text each constant slot was assembled from, to catch listings that
+    // give two different values for the same index.
+    constants: HashMap<usize, String>,
+}
+
+/// A jump to a label, patched once all labels are known.
+struct Fixup {
+    offset: usize,
+    label: String,
+    listing_line: usize,
+}
+
+impl Assembler {
+    fn assemble_line(&mut self, text: &str, listing_line: usize) -> Result<(), String> {
+        let (text, comment) = split_comment(text);
+        if let Some(line) = comment.and_then(|comment| comment.trim().strip_prefix("line ")) {
+            let line = line
+                .trim()
+                .parse()
+                .map_err(|_| format!("Bad line annotation {}", line.trim()))?;
+            self.set_line(line);
+        }
+        let mut rest = text.trim();
+        if rest.is_empty() {
+            return Ok(());
+        }
+        if let Some(directive) = rest.strip_prefix('.') {
+            return self.directive(directive);
+        }
+        if let Some(label) = rest.strip_suffix(':') {
+            return self.define_label(label);
+        }
+
+        let (first, after_first) = next_word(rest);
+        if first.bytes().all(|b| b.is_ascii_digit()) {
+            let offset: usize = first.parse().map_err(|_| format!("Bad offset {}", first))?;
+            if offset != self.chunk.code.len() {
+                return Err(format!(
+                    "Listed offset {} does not match actual offset {}",
+                    offset,
+                    self.chunk.code.len()
+                ));
+            }
+            let (line, after_line) = next_word(after_first);
+            match line {
+                "|" => {}
+                "?" => self.line_info = None,
+                line => self.set_line(line.parse().map_err(|_| format!("Bad line {}", line))?),
+            }
+            rest = after_line;
+        }
+
+        let (mnemonic, operand_text) = next_word(rest);
+        let op = *OpCode::all()
+            .iter()
+            .find(|op| op.info().mnemonic == mnemonic)
+            .ok_or_else(|| format!("Unknown mnemonic {}", mnemonic))?;
+        let operand = match &op.info().operand {
+            None if operand_text.is_empty() => None,
+            None => return Err(format!("{} takes no operand", mnemonic)),
+            Some(operand) => {
+                let value = match operand.kind {
+                    OperandKind::Constant => {
+                        self.constant_operand(operand_text, Some(operand.width))?
+                    }
+                    OperandKind::JumpForward | OperandKind::JumpBackward => {
+                        self.jump_operand(op, operand_text, listing_line)?
+                    }
+                };
+                if operand.width < 8 && value >> (8 * operand.width) != 0 {
+                    return Err(format!(
+                        "Operand {} does not fit in {} bytes",
+                        value, operand.width
+                    ));
+                }
+                Some(value)
+            }
+        };
+        let instruction = Instruction::new(op, operand).expect("operand matches the opcode");
+        self.chunk.push_instruction(instruction, self.line_info);
+        Ok(())
+    }
+
+    /// Moves to `line`, keeping the current span if it starts on that line.
+    fn set_line(&mut self, line: usize) {
+        if self.line_info.map(|info| info.line) == Some(line) {
+            return;
+        }
+        let source = match self.source {
+            Some(source) => source,
+            None => *self.source.insert(self.chunk.add_source(&self.source_name)),
+        };
+        self.line_info = Some(LineInfo::new(source, line, 0));
+    }
+
+    fn directive(&mut self, text: &str) -> Result<(), String> {
+        let (name, arguments) = next_word(text);
+        match name {
+            "file" => {
+                let source = self.chunk.add_source(&unescape(arguments)?);
+                // Line numbers refer to the first file a listing names.
+                self.source.get_or_insert(source);
+            }
+            "constant" => {
+                let (index, _) = next_word(arguments);
+                if !index.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(format!("Bad constant index {}", index));
+                }
+                self.constant_operand(arguments, None)?;
+            }
+            "span" if arguments == "none" => self.line_info = None,
+            "span" => self.line_info = Some(self.span(arguments)?),
+            _ => return Err(format!("Unknown directive .{}", name)),
+        }
+        Ok(())
+    }
+
+    /// Reads a span written as `file line:column-end_line:end_column`.
+    fn span(&self, text: &str) -> Result<LineInfo, String> {
+        let bad = || format!("Bad span {}", text);
+        let (file, range) = next_word(text);
+        let file: usize = file.parse().map_err(|_| bad())?;
+        let source = self
+            .chunk
+            .source_map
+            .file_id(file)
+            .ok_or_else(|| format!("Unknown file {}", file))?;
+        let (start, end) = range.split_once('-').ok_or_else(bad)?;
+        let position = |text: &str| -> Result<(usize, usize), String> {
+            let (line, column) = text.split_once(':').ok_or_else(bad)?;
+            Ok((
+                line.parse().map_err(|_| bad())?,
+                column.parse().map_err(|_| bad())?,
+            ))
+        };
+        let (line, column) = position(start)?;
+        let (end_line, end_column) = position(end)?;
+        Ok(LineInfo::new(source, line, column).with_end(end_line, end_column))
+    }
+
+    fn define_label(&mut self, label: &str) -> Result<(), String> {
+        if !is_label(label) {
+            return Err(format!("Bad label {}", label));
+        }
+        let offset = self.chunk.code.len();
+        if self.labels.insert(label.to_string(), offset).is_some() {
+            return Err(format!("Duplicate label {}", label));
+        }
+        Ok(())
+    }
+
+    /// Reads either a label, patched later, or a distance and the target it
+    /// leads to as written by `describe`.
+    fn jump_operand(
+        &mut self,
+        op: OpCode,
+        text: &str,
+        listing_line: usize,
+    ) -> Result<usize, String> {
+        if is_label(text) && !text.bytes().all(|b| b.is_ascii_digit()) {
+            self.fixups.push(Fixup {
+                offset: self.chunk.code.len(),
+                label: text.to_string(),
+                listing_line,
+            });
+            return Ok(0);
+        }
+        let (distance, target) = text
+            .split_once("->")
+            .ok_or_else(|| format!("Bad jump {}", text))?;
+        let distance: usize = distance
+            .trim()
+            .parse()
+            .map_err(|_| format!("Bad jump distance {}", distance.trim()))?;
+        let target: usize = target
+            .trim()
+            .parse()
+            .map_err(|_| format!("Bad jump target {}", target.trim()))?;
+        let instruction = Instruction::new(op, Some(distance)).expect("op takes an operand");
+        if instruction.jump_target(self.chunk.code.len()) != Some(target) {
+            return Err(format!(
+                "{} {} does not lead to {}",
+                op.info().mnemonic,
+                distance,
+                target
+            ));
+        }
+        Ok(distance)
+    }
+
+    fn resolve_labels(&mut self) -> Result<(), AssembleError> {
+        for fixup in &self.fixups {
+            let error = |message| AssembleError {
+                line: fixup.listing_line,
+                message,
+            };
+            let target = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| error(format!("Unknown label {}", fixup.label)))?;
+            let placeholder = self
+                .chunk
+                .instruction_at(fixup.offset)
+                .expect("fixups point at code")
+                .expect("placeholder decodes");
+            let next = fixup.offset + placeholder.length();
+            let distance = match &placeholder.info().operand {
+                Some(operand) if operand.kind == OperandKind::JumpBackward => {
+                    next.checked_sub(target)
+                }
+                _ => target.checked_sub(next),
+            }
+            .ok_or_else(|| {
+                error(format!(
+                    "{} cannot jump to label {} in that direction",
+                    placeholder.info().mnemonic,
+                    fixup.label
+                ))
+            })?;
+            if distance > MAX_JUMP {
+                return Err(error(format!("Label {} is too far away", fixup.label)));
+            }
+            let mut code = vec![];
+            Instruction::new(placeholder.op_code(), Some(distance))
+                .expect("op takes an operand")
+                .encode(&mut code);
+            self.chunk.code[fixup.offset..next].copy_from_slice(&code);
+        }
+        Ok(())
+    }
+
+    /// Reads a constant, with or without the index it goes at. An index has
+    /// to fit `width` operand bytes, if given, and the pool.
+    fn constant_operand(&mut self, text: &str, width: Option<usize>) -> Result<usize, String> {
+        let (first, value_text) = next_word(text);
+        if !value_text.is_empty() && first.bytes().all(|b| b.is_ascii_digit()) {
+            let index: usize = first
+                .parse()
+                .map_err(|_| format!("Bad constant index {}", first))?;
+            if index >= MAX_CONSTANTS {
+                return Err(format!("Constant index {} is too large", index));
+            }
+            if let Some(width) = width.filter(|width| index >> (8 * width) != 0) {
+                return Err(format!("Operand {} does not fit in {} bytes", index, width));
+            }
+            let value = parse_value(&self.chunk, value_text)?;
+            match self.constants.get(&index) {
+                Some(previous) if previous != value_text => Err(format!(
+                    "Constant {} is both {} and {}",
+                    index, previous, value_text
+                )),
+                Some(_) => Ok(index),
+                None => {
+                    while self.chunk.constants.len() <= index {
+                        self.chunk.constants.append(RTValue::null());
+                    }
+                    self.chunk.constants.set(index, value);
+                    self.constants.insert(index, value_text.to_string());
+                    Ok(index)
+                }
+            }
+        } else {
+            let value = parse_value(&self.chunk, text)?;
+            let index = self.chunk.push_constant(value);
+            self.constants.insert(index, text.to_string());
+            Ok(index)
+        }
+    }
+}
+
+fn parse_value(chunk: &Chunk, text: &str) -> Result<RTValue, String> {
+    let text = text.trim();
+    if text == "Null" || text == "nil" {
+        return Ok(RTValue::null());
+    }
+    if let Some(number) = text
+        .strip_prefix("Number(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return number
+            .parse()
+            .map(RTValue::number)
+            .map_err(|_| format!("Bad number {}", number));
+    }
+    let string = text
+        .strip_prefix("String(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .unwrap_or(text);
+    if string.starts_with('"') {
+        return Ok(RTValue::string(chunk.interner.intern(&unescape(string)?)));
+    }
+    text.parse()
+        .map(RTValue::number)
+        .map_err(|_| format!("Bad constant {}", text))
+}
+
+/// Reads a string literal as written by `{:?}`.
+fn unescape(quoted: &str) -> Result<String, String> {
+    let bad = || format!("Bad string literal {}", quoted);
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(bad)?;
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        result.push(match chars.next().ok_or_else(bad)? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            'u' => {
+                let digits: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let digits = digits.strip_prefix('{').ok_or_else(bad)?;
+                u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(bad)?
+            }
+            _ => return Err(bad()),
+        });
+    }
+    Ok(result)
+}
+
+fn split_comment(text: &str) -> (&str, Option<&str>) {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return (&text[..index], Some(&text[index + 1..])),
+            _ => {}
+        }
+    }
+    (text, None)
+}
+
+fn is_label(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c == '_' || c.is_alphanumeric())
+}
+
+fn next_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        bytecode::{Chunk, LineInfo, OpCode},
+        value::RTValue,
+    };
+
+    fn line_infos(chunk: &Chunk) -> Vec<Option<&LineInfo>> {
+        (0..chunk.code().len())
+            .map(|offset| chunk.get_line_info(offset))
+            .collect()
+    }
+
+    /// Checks that `describe` output assembles into a chunk equal to
+    /// `chunk` in code, file table, constant pool and line info.
+    fn assert_round_trips(chunk: &Chunk, source_name: &str) -> String {
+        let listing = chunk.describe_to_string();
+        let assembled = Chunk::assemble(&listing, source_name).unwrap();
+        assert_eq!(assembled.code(), chunk.code());
+        assert_eq!(assembled.source_map().files(), chunk.source_map().files());
+        let constants = |chunk: &Chunk| -> Vec<String> {
+            chunk
+                .constants()
+                .iter()
+                .map(|value| chunk.describe_value(value))
+                .collect()
+        };
+        assert_eq!(constants(&assembled), constants(chunk));
+        assert_eq!(line_infos(&assembled), line_infos(chunk));
+        assert_eq!(assembled.describe_to_string(), listing);
+        listing
+    }
+
+    #[test]
+    fn round_trips_descriptions() {
+        let mut chunk = Chunk::new();
+        let source = chunk.add_source("test");
+        let info = |line| Some(LineInfo::new(source, line, 0));
+        chunk.push_constant_and_load_op(RTValue::number(-0.0), info(1));
+        chunk.push_constant_and_load_op(RTValue::null(), None);
+        let string = chunk.push_string_constant("say \"hi\";\n\tbye \u{301}");
+        chunk.push_load_constant_op(string, info(2));
+        for n in 0..300 {
+            chunk.push_constant_and_load_op(RTValue::number(n as f64 / 7.0), info(3 + n / 100));
+        }
+        chunk.push_constant_and_load_op(RTValue::number(f64::NAN), info(3));
+        chunk.push_load_constant_op(string, info(3));
+        let jump = chunk.push_jump(OpCode::JumpIfFalse, info(4));
+        chunk.push_op_code(OpCode::Pop, info(4));
+        chunk.push_loop(0, None);
+        chunk.patch_jump(jump);
+        chunk.push_op_code(OpCode::Return, info(9));
+
+        // Assembled under another name, since the listing names its file.
+        let listing = assert_round_trips(&chunk, "elsewhere");
+        assert!(listing.starts_with(".file \"test\"\n0000    1 Constant    0 Number(-0.0)\n"));
+    }
+
+    #[test]
+    fn round_trips_what_instruction_lines_leave_out() {
+        let mut chunk = Chunk::new();
+        let main = chunk.add_source("main.lox");
+        let other = chunk.add_source("other \"lib\".lox");
+        chunk.add_source("unused.lox");
+        chunk.push_constant(RTValue::number(7.0));
+        chunk.push_constant_and_load_op(
+            RTValue::number(1.5),
+            Some(LineInfo::new(main, 3, 4).with_end(3, 6)),
+        );
+        chunk.push_constant_and_load_op(RTValue::null(), Some(LineInfo::new(other, 3, 0)));
+        chunk.push_op_code(OpCode::Negate, None);
+        let string = chunk.push_string_constant("s");
+        chunk.push_load_constant_op(string, Some(LineInfo::new(main, 3, 9)));
+        chunk.push_op_code(OpCode::Negate, Some(LineInfo::new(main, 3, 9)));
+        chunk.push_op_code(OpCode::Return, Some(LineInfo::new(main, 4, 0)));
+        chunk.push_op_code(
+            OpCode::Return,
+            Some(LineInfo::new(main, 5, 1).with_end(6, 2)),
+        );
+        chunk.push_constant(RTValue::number(8.0));
+
+        assert_eq!(
+            assert_round_trips(&chunk, "ignored"),
+            ".file \"main.lox\"\n\
+             .file \"other \\\"lib\\\".lox\"\n\
+             .file \"unused.lox\"\n\
+             .constant    0 Number(7.0)\n\
+             .constant    4 Number(8.0)\n\
+             .span 0 3:4-3:6\n\
+             0000    3 Constant    1 Number(1.5)\n\
+             .span 1 3:0-3:0\n\
+             0002    3 Constant    2 Null\n\
+             0004    ? Negate\n\
+             .span 0 3:9-3:9\n\
+             0005    3 Constant    3 String(\"s\")\n\
+             0007    | Negate\n\
+             0008    4 Return\n\
+             .span 0 5:1-6:2\n\
+             0009    5 Return\n"
+        );
+    }
+
+    #[test]
+    fn assembles_hand_written_listings() {
+        let chunk = Chunk::assemble(
+            "start: ; the beginning\n\
+             ; line 4\n\
+             Constant 1.5\n\
+             JumpIfFalse end\n\
+             \n\
+             ConstantLong \"a;b\" ; not a comment inside the string\n\
+             Loop start\n\
+             end:\n\
+             ; line 5\n\
+             Return",
+            "hand",
+        )
+        .unwrap();
+        assert_eq!(
+            chunk.describe_to_string(),
+            ".file \"hand\"\n\
+             0000    4 Constant    0 Number(1.5)\n\
+             0002    | JumpIfFalse    7 -> 0012\n\
+             0005    | ConstantLong    1 String(\"a;b\")\n\
+             0009    | Loop   12 -> 0000\n\
+             0012    5 Return\n"
+        );
+        let line_info = chunk.get_line_info(0).unwrap();
+        assert_eq!(chunk.source_name(line_info.source), Some("hand"));
+        assert_eq!((line_info.line, line_info.column), (4, 0));
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = |listing| Chunk::assemble(listing, "bad").err().unwrap().to_string();
+        assert_eq!(error("Return\nPush 1"), "line 2: Unknown mnemonic Push");
+        assert_eq!(error("Return 1"), "line 1: Return takes no operand");
+        assert_eq!(error("Constant"), "line 1: Bad constant ");
+        assert_eq!(
+            error("0001    ? Return"),
+            "line 1: Listed offset 1 does not match actual offset 0"
+        );
+        assert_eq!(
+            error("Constant 300 Null"),
+            "line 1: Operand 300 does not fit in 1 bytes"
+        );
+        // Indices are checked before the pool grows to hold them.
+        assert_eq!(
+            error("ConstantLong 4000000000 Null"),
+            "line 1: Constant index 4000000000 is too large"
+        );
+        assert_eq!(
+            error("ConstantLong 16777216 Null"),
+            "line 1: Constant index 16777216 is too large"
+        );
+        assert_eq!(
+            error("Constant 0 Number(1.0)\nConstant 0 Number(2.0)"),
+            "line 2: Constant 0 is both Number(1.0) and Number(2.0)"
+        );
+        assert_eq!(error("a:\na:"), "line 2: Duplicate label a");
+        assert_eq!(error("Jump b\nReturn"), "line 1: Unknown label b");
+        assert_eq!(
+            error("a:\nJump a"),
+            "line 2: Jump cannot jump to label a in that direction"
+        );
+        assert_eq!(
+            error("0000    ? Jump    1 -> 0003"),
+            "line 1: Jump 1 does not lead to 3"
+        );
+        assert_eq!(
+            error(".frobnicate"),
+            "line 1: Unknown directive .frobnicate"
+        );
+        assert_eq!(
+            error(".constant 99999999999 Null"),
+            "line 1: Constant index 99999999999 is too large"
+        );
+        assert_eq!(error(".constant x Null"), "line 1: Bad constant index x");
+        assert_eq!(error(".span 0 1:0-1:0"), "line 1: Unknown file 0");
+        assert_eq!(error(".file \"a\"\n.span 0 1:0"), "line 2: Bad span 0 1:0");
+        assert_eq!(
+            error("0000    1 Constant    0 <BAD INDEX>"),
+            "line 1: Bad constant <BAD INDEX>"
+        );
+    }
+}
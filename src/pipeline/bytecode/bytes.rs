@@ -80,8 +80,10 @@ impl_FromBytes_via!(u64, 1, u8);
 impl_FromBytes_via!(u64, 2, u16);
 impl_FromBytes_via!(u64, 4, u32);
 impl_FromBytes!(u64, 8);
+impl_FromBytes!(u32, 3);
 impl_FromBytes_via!(usize, 1, u8);
 impl_FromBytes_via!(usize, 2, u16);
+impl_FromBytes!(usize, 3);
 impl_FromBytes!(usize, mem::size_of::<usize>());
 
 macro_rules! impl_ToBytes {
@@ -110,15 +112,39 @@ impl_ToBytes!(8, u32);
 impl_ToBytes!(8, u64);
 impl_ToBytes!(mem::size_of::<usize>(), usize);
 
+// Encodes a number in fewer bytes than its type has, for operands with a fixed
+// width independent of the host. The number must fit in the narrower width.
+macro_rules! impl_ToBytes_narrowing {
+    ($size:expr, $num:ty) => {
+        impl ToBytes<{ $size }> for $num {
+            #[inline(always)]
+            fn num_to_bytes(&self) -> [u8; $size] {
+                let bytes = self.to_be_bytes();
+                let (dropped, kept) = bytes.split_at(bytes.len() - $size);
+                debug_assert!(
+                    dropped.iter().all(|byte| *byte == 0),
+                    "{} does not fit in {} bytes",
+                    self,
+                    $size
+                );
+                let mut result: [u8; $size] = [0; $size];
+                result.copy_from_slice(kept);
+                result
+            }
+        }
+    };
+}
+
+impl_ToBytes_narrowing!(3, u32);
+impl_ToBytes_narrowing!(1, usize);
+impl_ToBytes_narrowing!(2, usize);
+impl_ToBytes_narrowing!(3, usize);
+
 #[inline(always)]
 pub fn all_there<const N: usize>(some_bytes: &[Option<u8>; N]) -> Option<[u8; N]> {
     let mut result = [0; N];
-    for (index, place) in result.iter_mut().enumerate() {
-        if let Some(byte) = some_bytes[index] {
-            *place = byte;
-        } else {
-            return None;
-        }
+    for (place, byte) in result.iter_mut().zip(some_bytes) {
+        *place = (*byte)?;
     }
     Some(result)
 }
@@ -135,4 +161,26 @@ mod tests {
         assert_eq!(1234u16, ToBytes::<2>::num_to_bytes(&1234u16).bytes_to_num());
         assert_eq!(1234u32, ToBytes::<4>::num_to_bytes(&1234u16).bytes_to_num());
     }
+
+    #[test]
+    fn round_trip_narrow() {
+        assert_eq!(
+            [0x12, 0x34, 0x56],
+            ToBytes::<3>::num_to_bytes(&0x123456usize)
+        );
+        assert_eq!([0, 1, 44], ToBytes::<3>::num_to_bytes(&300u32));
+        for n in [0usize, 255, 256, 65535, 65536, 0xFF_FFFF] {
+            let narrow: usize = ToBytes::<3>::num_to_bytes(&n).bytes_to_num();
+            assert_eq!(n, narrow);
+            let narrow: u32 = ToBytes::<3>::num_to_bytes(&(n as u32)).bytes_to_num();
+            assert_eq!(n as u32, narrow);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit in 3 bytes")]
+    #[cfg(debug_assertions)]
+    fn narrowing_overflow() {
+        ToBytes::<3>::num_to_bytes(&0x100_0000usize);
+    }
 }
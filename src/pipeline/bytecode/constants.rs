@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use crate::pipeline::{
+    interner::Symbol,
+    value::{RTValue, Unpacked},
+};
+
+use super::ConstantIndex;
+
+/// What a constant is, as far as the serializer and the disassembler care.
+/// Function prototypes will join these once the compiler emits functions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConstantKind {
+    Null,
+    Number,
+    String,
+}
+
+impl ConstantKind {
+    pub fn of(value: &RTValue) -> Self {
+        match value.unpack() {
+            Unpacked::Null => ConstantKind::Null,
+            Unpacked::Number(_) => ConstantKind::Number,
+            Unpacked::String(_) => ConstantKind::String,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConstantKind::Null => "null",
+            ConstantKind::Number => "number",
+            ConstantKind::String => "string",
+        }
+    }
+}
+
+/// Identifies constants that behave the same. Numbers are compared by their
+/// bits, so `0.0` and `-0.0` stay apart, except that every NaN is the same
+/// constant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Null,
+    Number(u64),
+    String(Symbol),
+}
+
+impl ConstantKey {
+    fn of(value: &RTValue) -> Self {
+        match value.unpack() {
+            Unpacked::Null => ConstantKey::Null,
+            Unpacked::Number(number) if number.is_nan() => ConstantKey::Number(f64::NAN.to_bits()),
+            Unpacked::Number(number) => ConstantKey::Number(number.to_bits()),
+            Unpacked::String(symbol) => ConstantKey::String(symbol),
+        }
+    }
+}
+
+/// A chunk's constants, stored once each.
+#[derive(Default)]
+pub struct ConstantPool {
+    values: Vec<RTValue>,
+    indexes: HashMap<ConstantKey, ConstantIndex>,
+}
+
+impl ConstantPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn get(&self, index: ConstantIndex) -> Option<&RTValue> {
+        self.values.get(index)
+    }
+
+    /// # Safety
+    ///
+    /// `index` must be less than `len()`.
+    #[inline(always)]
+    pub unsafe fn get_unchecked(&self, index: ConstantIndex) -> &RTValue {
+        unsafe { self.values.get_unchecked(index) }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RTValue> {
+        self.values.iter()
+    }
+
+    /// The index of a constant equivalent to `value`, if there is one.
+    pub fn index_of(&self, value: &RTValue) -> Option<ConstantIndex> {
+        self.indexes.get(&ConstantKey::of(value)).copied()
+    }
+
+    /// Returns the index of an equivalent constant, adding `value` if there
+    /// is none yet.
+    pub fn insert(&mut self, value: RTValue) -> ConstantIndex {
+        let key = ConstantKey::of(&value);
+        if let Some(index) = self.indexes.get(&key) {
+            return *index;
+        }
+        self.append(value)
+    }
+
+    /// Adds `value` even if an equivalent constant exists, for loaders that
+    /// must keep the indices they were given.
+    pub(super) fn append(&mut self, value: RTValue) -> ConstantIndex {
+        let index = self.values.len();
+        self.indexes.entry(ConstantKey::of(&value)).or_insert(index);
+        self.values.push(value);
+        index
+    }
+
+    /// Replaces the constant at `index`, which must exist.
+    pub(super) fn set(&mut self, index: ConstantIndex, value: RTValue) {
+        let old_key = ConstantKey::of(&self.values[index]);
+        if self.indexes.get(&old_key) == Some(&index) {
+            self.indexes.remove(&old_key);
+            if let Some(other) = (0..self.values.len())
+                .find(|other| *other != index && ConstantKey::of(&self.values[*other]) == old_key)
+            {
+                self.indexes.insert(old_key, other);
+            }
+        }
+        self.indexes.entry(ConstantKey::of(&value)).or_insert(index);
+        self.values[index] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{interner::Interner, value::RTValue};
+
+    use super::{ConstantKind, ConstantPool};
+
+    #[test]
+    fn deduplicates_constants() {
+        let interner = Interner::new();
+        let mut pool = ConstantPool::new();
+        assert_eq!(pool.insert(RTValue::number(1.5)), 0);
+        assert_eq!(pool.insert(RTValue::number(0.0)), 1);
+        assert_eq!(pool.insert(RTValue::number(-0.0)), 2);
+        assert_eq!(pool.insert(RTValue::number(f64::NAN)), 3);
+        assert_eq!(pool.insert(RTValue::number(-f64::NAN)), 3);
+        assert_eq!(pool.insert(RTValue::string(interner.intern("a"))), 4);
+        assert_eq!(pool.insert(RTValue::null()), 5);
+        assert_eq!(pool.insert(RTValue::number(1.5)), 0);
+        assert_eq!(pool.insert(RTValue::string(interner.intern("a"))), 4);
+        assert_eq!(pool.insert(RTValue::null()), 5);
+        assert_eq!(pool.len(), 6);
+        assert_eq!(
+            pool.iter().map(ConstantKind::of).collect::<Vec<_>>(),
+            [
+                ConstantKind::Number,
+                ConstantKind::Number,
+                ConstantKind::Number,
+                ConstantKind::Number,
+                ConstantKind::String,
+                ConstantKind::Null
+            ]
+        );
+    }
+
+    #[test]
+    fn appends_and_replaces() {
+        let mut pool = ConstantPool::new();
+        assert_eq!(pool.append(RTValue::null()), 0);
+        assert_eq!(pool.append(RTValue::null()), 1);
+        pool.set(0, RTValue::number(2.0));
+        assert_eq!(pool.insert(RTValue::null()), 1);
+        assert_eq!(pool.insert(RTValue::number(2.0)), 0);
+        pool.set(1, RTValue::number(3.0));
+        assert_eq!(pool.insert(RTValue::null()), 2);
+    }
+}
@@ -1,109 +1,128 @@
 use std::io::{self, BufWriter, Write};
 
-use super::{
-    bytes::{self, FromBytes},
-    Chunk, ConstantIndex, OpCode, CONSTANT_LONG_ARG_BYTES,
-};
-
-impl<'s> Chunk<'s> {
-    fn write_line_prefix<W: io::Write>(&self, w: &mut W, offset: usize) {
-        write!(w, "{:0>4} ", offset).unwrap();
+use crate::pipeline::value::{RTValue, Unpacked};
+
+use super::{Chunk, DecodeError, Instruction, Operand, OperandKind};
+
+impl Chunk {
+    /// The offset and line columns of a description. The line is `|` when
+    /// it is the same line of the same source as the previous byte's, and
+    /// `?` when unknown.
+    pub(super) fn line_prefix(&self, offset: usize) -> String {
         let previous_line = offset
             .checked_sub(1)
-            .and_then(|offset| self.source_map.get_line_info(offset).map(|li| li.line));
+            .and_then(|offset| self.source_map.get_line_info(offset))
+            .map(|li| (li.source, li.line));
         match self.source_map.get_line_info(offset) {
-            Some(line_info) => {
-                if previous_line == Some(line_info.line) {
-                    write!(w, "   | ").unwrap();
-                } else {
-                    write!(w, "{:>4} ", line_info.line).unwrap();
-                }
-            }
-            None => {
-                write!(w, "   ? ").unwrap();
+            Some(line_info) if previous_line == Some((line_info.source, line_info.line)) => {
+                format!("{:0>4}    | ", offset)
             }
+            Some(line_info) => format!("{:0>4} {:>4} ", offset, line_info.line),
+            None => format!("{:0>4}    ? ", offset),
         }
     }
 
-    fn describe_simple<W: io::Write>(w: &mut W, op: &OpCode) {
-        writeln!(w, "{:?}", op).unwrap();
+    pub(super) fn describe_value(&self, value: &RTValue) -> String {
+        match value.unpack() {
+            Unpacked::String(symbol) => match self.interner.resolve(symbol) {
+                Some(string) => format!("String({:?})", string),
+                None => format!("String(<BAD SYMBOL {}>)", symbol.index()),
+            },
+            other => format!("{:?}", other),
+        }
     }
 
-    fn describe_constant<const N: usize, W: io::Write>(
-        &self,
-        w: &mut W,
-        op: &OpCode,
-        arg_bytes: [Option<u8>; N],
-    ) where
-        [u8; N]: FromBytes<ConstantIndex>,
+    /// Writes one line describing the instruction at `offset`, or nothing
+    /// if `offset` is past the end of the code.
+    pub fn describe_instruction<W>(&self, w: &mut W, offset: usize)
+    where
+        W: io::Write,
     {
-        match bytes::all_there(&arg_bytes).map(|bytes| bytes.bytes_to_num()) {
-            None => {
-                writeln!(
-                    w,
-                    "{:?} <BAD BYTES>{:?}",
-                    op,
-                    arg_bytes
-                        .iter()
-                        .map_while(|opt_byte| *opt_byte)
-                        .collect::<Vec<u8>>()
-                )
-                .unwrap();
+        if let Some(decoded) = self.instruction_at(offset) {
+            self.describe_decoded(w, offset, &decoded);
+        }
+    }
+
+    /// The mnemonic and the operand text of a description, which is empty
+    /// for instructions without operands.
+    pub(super) fn describe_parts(
+        &self,
+        offset: usize,
+        decoded: &Result<Instruction, DecodeError>,
+    ) -> (String, String) {
+        match decoded {
+            Ok(instruction) => {
+                let info = instruction.info();
+                let operands = match (&info.operand, instruction.operand()) {
+                    (
+                        Some(Operand {
+                            kind: OperandKind::Constant,
+                            ..
+                        }),
+                        Some(index),
+                    ) => match self.get_constant(index) {
+                        None => format!("{:>4} <BAD INDEX>", index),
+                        Some(constant_value) => {
+                            format!("{:>4} {}", index, self.describe_value(constant_value))
+                        }
+                    },
+                    (Some(_), Some(distance)) => match instruction.jump_target(offset) {
+                        Some(target) => format!("{:>4} -> {:0>4}", distance, target),
+                        None => format!("{:>4} -> <BAD TARGET>", distance),
+                    },
+                    _ => String::new(),
+                };
+                (info.mnemonic.to_string(), operands)
+            }
+            Err(DecodeError::MissingOperands { op, found, .. }) => (
+                op.info().mnemonic.to_string(),
+                format!(
+                    "<BAD BYTES>{:?}",
+                    &self.code[offset + 1..offset + 1 + found]
+                ),
+            ),
+            Err(DecodeError::UnknownOpCode(byte)) => {
+                ("Unknown".to_string(), format!("op {}", byte))
             }
-            Some(index) => match self.get_constant(index) {
-                None => {
-                    writeln!(w, "{:?} {:>4} <BAD INDEX>", op, index).unwrap();
-                }
-                Some(constant_value) => {
-                    writeln!(w, "{:?} {:>4} {:?}", op, index, constant_value).unwrap();
-                }
-            },
         }
     }
 
-    pub fn describe_instruction<'i, W>(
+    fn describe_decoded<W>(
         &self,
         w: &mut W,
         offset: usize,
-        op_byte: u8,
-        ops: &mut impl Iterator<Item = &'i u8>,
+        decoded: &Result<Instruction, DecodeError>,
     ) where
         W: io::Write,
     {
-        self.write_line_prefix(w, offset);
-        match OpCode::try_from(op_byte) {
-            Ok(op @ OpCode::Constant) => {
-                self.describe_constant(w, &op, bytes::try_next_bytes::<1, _, _>(ops, |b| *b))
-            }
-            Ok(op @ OpCode::ConstantLong) => self.describe_constant(
-                w,
-                &op,
-                bytes::try_next_bytes::<CONSTANT_LONG_ARG_BYTES, _, _>(ops, |b| *b),
-            ),
-            Ok(op) => Self::describe_simple(w, &op),
-            Err(byte) => {
-                writeln!(w, "Unknown op {}", byte).unwrap();
-            }
+        let (mnemonic, operands) = self.describe_parts(offset, decoded);
+        if operands.is_empty() {
+            writeln!(w, "{}{}", self.line_prefix(offset), mnemonic).unwrap();
+        } else {
+            writeln!(w, "{}{} {}", self.line_prefix(offset), mnemonic, operands).unwrap();
         }
     }
 
+    /// Writes a listing of the chunk that `Chunk::assemble` turns back into
+    /// the same chunk: one line per instruction, after any directives the
+    /// assembler needs to restore what those lines don't show.
     pub fn describe<W>(&self, w: &mut W)
     where
         W: io::Write,
     {
-        let mut ops = DebugIter::new(self.code.iter());
-        while let Some(op) = ops.next() {
-            self.describe_instruction(w, ops.offset() - 1, *op, &mut ops);
+        self.write_preamble(w).unwrap();
+        let mut previous = None;
+        for (offset, decoded) in self.instructions() {
+            if let Some(span) = self.span_directive(offset, previous) {
+                writeln!(w, "{}", span).unwrap();
+            }
+            previous = self.get_line_info(offset).copied();
+            self.describe_decoded(w, offset, &decoded);
         }
     }
 
-    pub fn describe_instruction_to_stderr<'i>(
-        &self,
-        offset: usize,
-        op_byte: u8,
-        ops: &mut impl Iterator<Item = &'i u8>,
-    ) {
-        self.describe_instruction(&mut io::stderr().lock(), offset, op_byte, ops)
+    pub fn describe_instruction_to_stderr(&self, offset: usize) {
+        self.describe_instruction(&mut io::stderr().lock(), offset)
     }
 
     pub fn describe_to_stderr(&self, chunk_name: Option<&str>) {
@@ -127,48 +146,23 @@ impl<'s> Chunk<'s> {
     }
 }
 
-struct DebugIter<I> {
-    inner: I,
-    offset: usize,
-}
-
-impl<I> DebugIter<I> {
-    fn new(inner: I) -> Self {
-        Self { inner, offset: 0 }
-    }
-
-    fn offset(&self) -> usize {
-        self.offset
-    }
-}
-
-impl<I> Iterator for DebugIter<I>
-where
-    I: Iterator,
-{
-    type Item = I::Item;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|v| {
-            self.offset += 1;
-            v
-        })
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use crate::pipeline::value::RTValue;
+    use crate::pipeline::interner::Interner;
 
-    use super::{super::LineInfo, *};
+    use super::{
+        super::{LineInfo, OpCode, MAX_CONSTANTS},
+        *,
+    };
 
     #[test]
     fn test_describe() {
-        let info = |line, col| Some(LineInfo::new("test", line, col));
         let mut chunk = Chunk::new();
+        let source = chunk.add_source("test");
+        let info = |line, col| Some(LineInfo::new(source, line, col));
         chunk.push_op_code(OpCode::Return, info(1, 1));
         chunk.push_op_code(OpCode::Return, None);
-        let constant_index = chunk.push_constant(RTValue::Number(42.0));
+        let constant_index = chunk.push_constant(RTValue::number(42.0));
         chunk.push_load_constant_op(constant_index, info(2, 3));
         chunk.push_op_code(OpCode::Return, info(2, 4));
         chunk.push_load_constant_op(300, info(3, 7));
@@ -176,12 +170,98 @@ mod tests {
         chunk.push_op_arg(7, info(7, 4));
         assert_eq!(
             chunk.describe_to_string(),
-            "0000    1 Return\n\
+            ".file \"test\"\n\
+             .span 0 1:1-1:1\n\
+             0000    1 Return\n\
              0001    ? Return\n\
+             .span 0 2:3-2:3\n\
              0002    2 Constant    0 Number(42.0)\n\
+             .span 0 2:4-2:4\n\
              0004    | Return\n\
+             .span 0 3:7-3:7\n\
              0005    3 ConstantLong  300 <BAD INDEX>\n\
-             0014    7 ConstantLong <BAD BYTES>[7]\n\
+             .span 0 7:4-7:4\n\
+             0009    7 ConstantLong <BAD BYTES>[7]\n\
+             "
+        );
+    }
+
+    #[test]
+    fn test_describe_constant_long() {
+        let mut chunk = Chunk::new();
+        for n in 0..70_000 {
+            chunk.push_constant(RTValue::number(n as f64));
+        }
+        chunk.push_load_constant_op(255, None);
+        chunk.push_load_constant_op(256, None);
+        chunk.push_load_constant_op(69_999, None);
+        assert_eq!(
+            chunk.code(),
+            [
+                OpCode::Constant as u8,
+                255,
+                OpCode::ConstantLong as u8,
+                0,
+                1,
+                0,
+                OpCode::ConstantLong as u8,
+                1,
+                17,
+                111
+            ]
+        );
+        // Every other constant is listed first, as a directive.
+        let description = chunk.describe_to_string();
+        assert_eq!(description.lines().count(), 70_000);
+        assert_eq!(
+            description.split_once("\n0000").unwrap().1,
+            "    ? Constant  255 Number(255.0)\n\
+             0002    ? ConstantLong  256 Number(256.0)\n\
+             0006    ? ConstantLong 69999 Number(69999.0)\n\
+             "
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit in a ConstantLong operand")]
+    fn test_constant_index_too_large() {
+        Chunk::new().push_load_constant_op(MAX_CONSTANTS, None);
+    }
+
+    #[test]
+    fn test_describe_jumps() {
+        let mut chunk = Chunk::new();
+        let jump = chunk.push_jump(OpCode::JumpIfFalse, None);
+        chunk.push_op_code(OpCode::Pop, None);
+        chunk.patch_jump(jump);
+        chunk.push_loop(0, None);
+        chunk.push_instruction(Instruction::Loop(20), None);
+        assert_eq!(
+            chunk.describe_to_string(),
+            "0000    ? JumpIfFalse    1 -> 0004\n\
+             0003    ? Pop\n\
+             0004    ? Loop    7 -> 0000\n\
+             0007    ? Loop   20 -> <BAD TARGET>\n\
+             "
+        );
+    }
+
+    #[test]
+    fn test_describe_strings() {
+        let interner = Interner::new();
+        let mut chunk = Chunk::with_interner(interner.clone());
+        let name = chunk.push_string_constant("name");
+        chunk.push_load_constant_op(name, None);
+        chunk.push_constant_and_load_op(RTValue::string(interner.intern("name")), None);
+        let foreign = Interner::new();
+        foreign.intern("a");
+        chunk.push_constant_and_load_op(RTValue::string(foreign.intern("b")), None);
+        assert_eq!(interner.len(), 1);
+        assert_eq!(
+            chunk.describe_to_string(),
+            "0000    ? Constant    0 String(\"name\")\n\
+             0002    ? Constant    0 String(\"name\")\n\
+             0004    ? Constant    1 String(<BAD SYMBOL 1>)\n\
              "
         );
     }
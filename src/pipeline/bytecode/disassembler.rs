@@ -0,0 +1,495 @@
+//! An annotated disassembly for people and tools. Compared to
+//! `Chunk::describe`, the text form labels jump targets, draws jumps as
+//! arrows in a left gutter, shows the source line next to its first
+//! instruction and can be colored with ANSI escapes:
+//!
+//! ```text
+//!     0000    1 Constant    0 Null            ; while (nil) {
+//! ,-> L0:
+//! |,- 0002    | JumpIfFalse    4 -> 0009 (L1)
+//! ||  0005    | Pop
+//! `+- 0006    | Loop    7 -> 0002 (L0)
+//!  `> L1:
+//!     0009    3 Return                        ; }
+//! ```
+//!
+//! The JSON form has one object per chunk on a single line, with the
+//! constant pool, the labels and every instruction with its bytes, operand,
+//! jump target and line info. Numbers that JSON cannot represent, like NaN,
+//! are written as strings.
+
+use std::{collections::HashMap, io};
+
+use crate::pipeline::{
+    source::Source,
+    value::{RTValue, Unpacked},
+};
+
+use super::{Chunk, ConstantKind, DecodeError, Instruction};
+
+/// Column at which source text starts in the text form.
+const SOURCE_COLUMN: usize = 40;
+
+const STYLE_PREFIX: &str = "2";
+const STYLE_MNEMONIC: &str = "1;36";
+const STYLE_OPERANDS: &str = "33";
+const STYLE_ERROR: &str = "1;31";
+const STYLE_LABEL: &str = "1;35";
+const STYLE_ARROW: &str = "35";
+const STYLE_SOURCE: &str = "2";
+
+pub struct Disassembler<'c> {
+    chunk: &'c Chunk,
+    name: Option<&'c str>,
+    sources: HashMap<&'c str, &'c Source>,
+    color: bool,
+}
+
+enum Row<'c> {
+    Label(&'c str),
+    Instruction(usize, Result<Instruction, DecodeError>),
+}
+
+/// A jump drawn from the row of the jump instruction to the row of its
+/// target's label, in a gutter lane numbered from the innermost.
+struct Arrow {
+    from: usize,
+    to: usize,
+    lane: usize,
+}
+
+impl Arrow {
+    fn rows(&self) -> (usize, usize) {
+        (self.from.min(self.to), self.from.max(self.to))
+    }
+}
+
+impl<'c> Disassembler<'c> {
+    pub fn new(chunk: &'c Chunk) -> Self {
+        Self {
+            chunk,
+            name: None,
+            sources: HashMap::new(),
+            color: false,
+        }
+    }
+
+    /// Heads the text form with `== name ==` and names the JSON object.
+    pub fn with_name(self, name: &'c str) -> Self {
+        Self {
+            name: Some(name),
+            ..self
+        }
+    }
+
+    /// Shows lines of `source` next to instructions whose line info names
+    /// it.
+    pub fn with_source(mut self, source: &'c Source) -> Self {
+        self.sources.insert(source.name(), source);
+        self
+    }
+
+    pub fn with_color(self, color: bool) -> Self {
+        Self { color, ..self }
+    }
+
+    /// Names jump targets `L0`, `L1`, ... in code order. Targets past the
+    /// end of the code are labelled too, but targets inside an instruction
+    /// or before the start of the code are not.
+    fn labels(&self) -> Vec<(usize, String)> {
+        let starts: Vec<usize> = self
+            .chunk
+            .instructions()
+            .map(|(offset, _)| offset)
+            .chain([self.chunk.code.len()])
+            .collect();
+        let mut targets: Vec<usize> = self
+            .chunk
+            .instructions()
+            .filter_map(|(offset, decoded)| decoded.ok()?.jump_target(offset))
+            .filter(|target| starts.binary_search(target).is_ok())
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+            .into_iter()
+            .enumerate()
+            .map(|(index, target)| (target, format!("L{}", index)))
+            .collect()
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn source_line(&self, offset: usize) -> Option<&'c str> {
+        let line_info = self.chunk.get_line_info(offset)?;
+        let source = self
+            .sources
+            .get(self.chunk.source_name(line_info.source)?)?;
+        source.text().lines().nth(line_info.line.checked_sub(1)?)
+    }
+
+    pub fn write_text<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let labels = self.labels();
+        let label_names: HashMap<usize, &str> = labels
+            .iter()
+            .map(|(offset, name)| (*offset, name.as_str()))
+            .collect();
+
+        let mut rows: Vec<Row> = vec![];
+        let mut label_rows: HashMap<usize, usize> = HashMap::new();
+        for (offset, decoded) in self.chunk.instructions() {
+            if let Some(name) = label_names.get(&offset) {
+                label_rows.insert(offset, rows.len());
+                rows.push(Row::Label(name));
+            }
+            rows.push(Row::Instruction(offset, decoded));
+        }
+        if let Some(name) = label_names.get(&self.chunk.code.len()) {
+            label_rows.insert(self.chunk.code.len(), rows.len());
+            rows.push(Row::Label(name));
+        }
+
+        let arrows = assign_lanes(
+            rows.iter()
+                .enumerate()
+                .filter_map(|(row, content)| match content {
+                    Row::Instruction(offset, Ok(instruction)) => {
+                        let target = instruction.jump_target(*offset)?;
+                        Some((row, *label_rows.get(&target)?))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        );
+        let lanes = arrows.iter().map(|arrow| arrow.lane + 1).max().unwrap_or(0);
+
+        if let Some(name) = self.name {
+            writeln!(w, "== {} ==", name)?;
+        }
+        for (row, content) in rows.iter().enumerate() {
+            let gutter = self.paint(STYLE_ARROW, &gutter(&arrows, lanes, row));
+            match content {
+                Row::Label(name) => {
+                    writeln!(
+                        w,
+                        "{}{}",
+                        gutter,
+                        self.paint(STYLE_LABEL, &format!("{}:", name))
+                    )?;
+                }
+                Row::Instruction(offset, decoded) => {
+                    let prefix = self.chunk.line_prefix(*offset);
+                    let (mnemonic, mut operands) = self.chunk.describe_parts(*offset, decoded);
+                    if let Some(name) = decoded
+                        .as_ref()
+                        .ok()
+                        .and_then(|instruction| instruction.jump_target(*offset))
+                        .and_then(|target| label_names.get(&target))
+                    {
+                        operands.push_str(&format!(" ({})", name));
+                    }
+                    let width = prefix.len() + mnemonic.len() + operands.len() + 1;
+                    let mnemonic = match decoded {
+                        Ok(_) => self.paint(STYLE_MNEMONIC, &mnemonic),
+                        Err(_) => self.paint(STYLE_ERROR, &mnemonic),
+                    };
+                    let mut line = format!(
+                        "{}{}{} {}",
+                        gutter,
+                        self.paint(STYLE_PREFIX, &prefix),
+                        mnemonic,
+                        self.paint(STYLE_OPERANDS, &operands)
+                    );
+                    let starts_line = !prefix.contains('|') && !prefix.contains('?');
+                    match self.source_line(*offset).filter(|_| starts_line) {
+                        Some(text) => {
+                            let padding = SOURCE_COLUMN.saturating_sub(width).max(1);
+                            let comment = format!("; {}", text.trim());
+                            line.push_str(&" ".repeat(padding));
+                            line.push_str(&self.paint(STYLE_SOURCE, &comment));
+                        }
+                        None => line.truncate(line.trim_end().len()),
+                    }
+                    writeln!(w, "{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "{{\"name\":")?;
+        match self.name {
+            Some(name) => write!(w, "{}", json_string(name))?,
+            None => write!(w, "null")?,
+        }
+
+        write!(w, ",\"constants\":[")?;
+        for (index, constant) in self.chunk.constants.iter().enumerate() {
+            if index > 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "{{\"index\":{},{}}}", index, self.json_value(constant))?;
+        }
+
+        let labels = self.labels();
+        write!(w, "],\"labels\":[")?;
+        for (index, (offset, name)) in labels.iter().enumerate() {
+            if index > 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "{{\"name\":\"{}\",\"offset\":{}}}", name, offset)?;
+        }
+
+        write!(w, "],\"instructions\":[")?;
+        for (index, (offset, decoded)) in self.chunk.instructions().enumerate() {
+            if index > 0 {
+                write!(w, ",")?;
+            }
+            let length = match &decoded {
+                Ok(instruction) => instruction.length(),
+                Err(DecodeError::UnknownOpCode(_)) => 1,
+                Err(DecodeError::MissingOperands { found, .. }) => 1 + found,
+            };
+            let bytes: Vec<String> = self.chunk.code[offset..offset + length]
+                .iter()
+                .map(u8::to_string)
+                .collect();
+            write!(w, "{{\"offset\":{},\"bytes\":[{}]", offset, bytes.join(","))?;
+            match &decoded {
+                Ok(instruction) => {
+                    write!(w, ",\"mnemonic\":\"{}\"", instruction.info().mnemonic)?;
+                    write!(w, ",\"operand\":{}", json_option(instruction.operand()))?;
+                    let target = instruction.jump_target(offset);
+                    write!(w, ",\"target\":{}", json_option(target))?;
+                    let label = target
+                        .and_then(|target| labels.iter().find(|(offset, _)| *offset == target))
+                        .map(|(_, name)| json_string(name));
+                    write!(w, ",\"label\":{}", label.as_deref().unwrap_or("null"))?;
+                }
+                Err(err) => write!(w, ",\"error\":{}", json_string(&err.to_string()))?,
+            }
+            match self.chunk.get_line_info(offset) {
+                Some(line_info) => {
+                    let source_name = self.chunk.source_name(line_info.source);
+                    write!(
+                        w,
+                        ",\"source_name\":{},\"line\":{},\"column\":{},\
+                         \"end_line\":{},\"end_column\":{}",
+                        source_name.map_or_else(|| "null".to_string(), json_string),
+                        line_info.line,
+                        line_info.column,
+                        line_info.end_line,
+                        line_info.end_column
+                    )?
+                }
+                None => write!(
+                    w,
+                    ",\"source_name\":null,\"line\":null,\"column\":null,\
+                     \"end_line\":null,\"end_column\":null"
+                )?,
+            }
+            match self.source_line(offset) {
+                Some(text) => write!(w, ",\"source\":{}}}", json_string(text))?,
+                None => write!(w, ",\"source\":null}}")?,
+            }
+        }
+        writeln!(w, "]}}")
+    }
+
+    fn json_value(&self, value: &RTValue) -> String {
+        let kind = format!("\"kind\":\"{}\"", ConstantKind::of(value).name());
+        match value.unpack() {
+            Unpacked::Null => kind,
+            Unpacked::Number(number) if number.is_finite() => {
+                format!("{},\"value\":{}", kind, number)
+            }
+            Unpacked::Number(number) => format!("{},\"value\":\"{}\"", kind, number),
+            Unpacked::String(symbol) => match self.chunk.interner.resolve(symbol) {
+                Some(string) => format!("{},\"value\":{}", kind, json_string(&string)),
+                None => format!("{},\"symbol\":{}", kind, symbol.index()),
+            },
+        }
+    }
+}
+
+/// Gives each arrow the innermost lane that is free along all of its rows,
+/// placing short arrows first so they end up inside longer ones.
+fn assign_lanes(mut jumps: Vec<(usize, usize)>) -> Vec<Arrow> {
+    jumps.sort_by_key(|(from, to)| from.abs_diff(*to));
+    let mut arrows: Vec<Arrow> = vec![];
+    for (from, to) in jumps {
+        let (low, high) = (from.min(to), from.max(to));
+        let lane = (0..)
+            .find(|lane| {
+                !arrows.iter().any(|arrow| {
+                    let (other_low, other_high) = arrow.rows();
+                    arrow.lane == *lane && other_low <= high && low <= other_high
+                })
+            })
+            .unwrap();
+        arrows.push(Arrow { from, to, lane });
+    }
+    arrows
+}
+
+/// The gutter for one row: a column per lane, outermost first, then a column
+/// for arrow heads. Empty when there are no arrows.
+fn gutter(arrows: &[Arrow], lanes: usize, row: usize) -> String {
+    if lanes == 0 {
+        return String::new();
+    }
+    let mut columns = vec![' '; lanes + 2];
+    let column = |lane: usize| lanes - 1 - lane;
+    for arrow in arrows {
+        let (low, high) = arrow.rows();
+        if low < row && row < high {
+            columns[column(arrow.lane)] = '|';
+        }
+    }
+    for arrow in arrows {
+        let (low, high) = arrow.rows();
+        if row != low && row != high {
+            continue;
+        }
+        let start = column(arrow.lane);
+        columns[start] = if row == low { ',' } else { '`' };
+        for c in &mut columns[start + 1..lanes] {
+            *c = match *c {
+                ' ' | '-' => '-',
+                _ => '+',
+            };
+        }
+        columns[lanes] = if row == arrow.to { '>' } else { '-' };
+    }
+    columns.into_iter().collect()
+}
+
+fn json_option(value: Option<usize>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+fn json_string(string: &str) -> String {
+    let mut result = String::with_capacity(string.len() + 2);
+    result.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        bytecode::{Chunk, LineInfo, OpCode},
+        source::Source,
+        value::RTValue,
+    };
+
+    use super::Disassembler;
+
+    fn sample_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        let source = chunk.add_source("loop.lox");
+        let info = |line| Some(LineInfo::new(source, line, 0));
+        chunk.push_constant_and_load_op(RTValue::null(), info(1));
+        let loop_start = chunk.code().len();
+        let exit = chunk.push_jump(OpCode::JumpIfFalse, info(1));
+        chunk.push_op_code(OpCode::Pop, info(1));
+        chunk.push_loop(loop_start, info(1));
+        chunk.patch_jump(exit);
+        chunk.push_op_code(OpCode::Return, info(3));
+        chunk
+    }
+
+    fn text(disassembler: &Disassembler) -> String {
+        let mut buf = vec![];
+        disassembler.write_text(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn draws_jumps_and_source() {
+        let chunk = sample_chunk();
+        let source = Source::new(
+            "loop.lox".to_string(),
+            "while (nil) {\n  // nothing\n}\n".to_string(),
+        );
+        assert_eq!(
+            text(&Disassembler::new(&chunk).with_source(&source)),
+            "    0000    1 Constant    0 Null            ; while (nil) {\n\
+             ,-> L0:\n\
+             |,- 0002    | JumpIfFalse    4 -> 0009 (L1)\n\
+             ||  0005    | Pop\n\
+             `+- 0006    | Loop    7 -> 0002 (L0)\n\
+             \x20`> L1:\n\
+             \x20   0009    3 Return                        ; }\n"
+        );
+    }
+
+    #[test]
+    fn plain_without_jumps() {
+        let mut chunk = Chunk::new();
+        chunk.push_constant_and_load_op(RTValue::number(1.0), None);
+        chunk.push_op_code(OpCode::Return, None);
+        let disassembler = Disassembler::new(&chunk).with_name("plain");
+        assert_eq!(
+            text(&disassembler),
+            format!("== plain ==\n{}", chunk.describe_to_string())
+        );
+        assert_eq!(
+            text(&disassembler.with_color(true)),
+            "== plain ==\n\
+             \x1b[2m0000    ? \x1b[0m\x1b[1;36mConstant\x1b[0m \x1b[33m   0 Number(1.0)\x1b[0m\n\
+             \x1b[2m0002    ? \x1b[0m\x1b[1;36mReturn\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let mut chunk = sample_chunk();
+        chunk.push_constant(RTValue::number(f64::NAN));
+        chunk.push_string_constant("a \"quoted\"\nline");
+        chunk.push_op_arg(200, None);
+        let mut buf = vec![];
+        Disassembler::new(&chunk)
+            .with_name("sample")
+            .write_json(&mut buf)
+            .unwrap();
+        let json = String::from_utf8(buf).unwrap();
+        assert_eq!(json.lines().count(), 1);
+        assert!(json.starts_with(
+            "{\"name\":\"sample\",\"constants\":[\
+             {\"index\":0,\"kind\":\"null\"},\
+             {\"index\":1,\"kind\":\"number\",\"value\":\"NaN\"},\
+             {\"index\":2,\"kind\":\"string\",\"value\":\"a \\\"quoted\\\"\\nline\"}],\
+             \"labels\":[{\"name\":\"L0\",\"offset\":2},{\"name\":\"L1\",\"offset\":9}],\
+             \"instructions\":[{\"offset\":0,\"bytes\":[1,0],\"mnemonic\":\"Constant\",\
+             \"operand\":0,\"target\":null,\"label\":null,\
+             \"source_name\":\"loop.lox\",\"line\":1,\"column\":0,\
+             \"end_line\":1,\"end_column\":0,\"source\":null},\
+             {\"offset\":2,\"bytes\":[5,0,4],\"mnemonic\":\"JumpIfFalse\",\
+             \"operand\":4,\"target\":9,\"label\":\"L1\","
+        ));
+        assert!(json.ends_with(
+            "{\"offset\":10,\"bytes\":[200],\"error\":\"Unknown op 200\",\
+             \"source_name\":null,\"line\":null,\"column\":null,\
+             \"end_line\":null,\"end_column\":null,\"source\":null}]}\n"
+        ));
+    }
+}
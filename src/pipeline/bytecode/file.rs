@@ -0,0 +1,357 @@
+//! The `.loxc` format for compiled chunks. All numbers are big-endian.
+//!
+//! ```text
+//! magic            b"LOXC"
+//! format version   u16
+//! constant count   u32, then per constant a u8 tag and its payload:
+//!                    TAG_NULL   (nothing)
+//!                    TAG_NUMBER u64 (f64 bits)
+//!                    TAG_STRING u32 length, UTF-8 bytes
+//! code             u32 length, bytes
+//! source names     u32 count, then per name a u32 length and UTF-8 bytes
+//! line info        u32 count, then per range u32 start, u32 end,
+//!                  u32 source name index, u32 line, u32 column,
+//!                  u32 end line, u32 end column
+//! ```
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Read, Write},
+};
+
+use crate::pipeline::{
+    interner::Interner,
+    value::{RTValue, Unpacked},
+};
+
+use super::{
+    bytes::{FromBytes, ToBytes},
+    Chunk, ConstantKind, LineInfo,
+};
+
+pub const MAGIC: [u8; 4] = *b"LOXC";
+pub const FORMAT_VERSION: u16 = 3;
+
+const TAG_NULL: u8 = 0;
+const TAG_NUMBER: u8 = 1;
+const TAG_STRING: u8 = 2;
+
+#[derive(Debug)]
+pub enum ChunkFileError {
+    Io(io::Error),
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    Truncated(&'static str),
+    BadConstantTag(u8),
+    BadUtf8(&'static str),
+    BadLineInfo(String),
+    TooLarge(&'static str),
+    UnknownSymbol(usize),
+}
+
+impl Display for ChunkFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkFileError::Io(err) => write!(f, "I/O error: {}", err),
+            ChunkFileError::BadMagic(magic) => {
+                write!(f, "Not a compiled Lox chunk (magic number {:?})", magic)
+            }
+            ChunkFileError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported chunk format version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            ChunkFileError::Truncated(section) => {
+                write!(f, "Chunk file is truncated in the {}", section)
+            }
+            ChunkFileError::BadConstantTag(tag) => write!(f, "Unknown constant tag {}", tag),
+            ChunkFileError::BadUtf8(section) => write!(f, "Invalid UTF-8 in the {}", section),
+            ChunkFileError::BadLineInfo(msg) => write!(f, "Invalid line info: {}", msg),
+            ChunkFileError::TooLarge(section) => {
+                write!(f, "The {} is too large for the chunk format", section)
+            }
+            ChunkFileError::UnknownSymbol(index) => {
+                write!(
+                    f,
+                    "String constant {} is not in the chunk's interner",
+                    index
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChunkFileError {}
+
+impl From<io::Error> for ChunkFileError {
+    fn from(err: io::Error) -> Self {
+        ChunkFileError::Io(err)
+    }
+}
+
+impl Chunk {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), ChunkFileError> {
+        w.write_all(&MAGIC)?;
+        w.write_all(&ToBytes::<2>::num_to_bytes(&FORMAT_VERSION))?;
+
+        write_u32(w, self.constants.len(), "constant pool")?;
+        for constant in self.constants.iter() {
+            w.write_all(&[kind_tag(ConstantKind::of(constant))])?;
+            match constant.unpack() {
+                Unpacked::Null => {}
+                Unpacked::Number(number) => {
+                    w.write_all(&ToBytes::<8>::num_to_bytes(&number.to_bits()))?;
+                }
+                Unpacked::String(symbol) => {
+                    let string = self
+                        .interner
+                        .resolve(symbol)
+                        .ok_or(ChunkFileError::UnknownSymbol(symbol.index()))?;
+                    write_bytes(w, string.as_bytes(), "constant pool")?;
+                }
+            }
+        }
+
+        write_bytes(w, &self.code, "code")?;
+
+        let files = self.source_map.files();
+        write_u32(w, files.len(), "source name table")?;
+        for name in files {
+            write_bytes(w, name.as_bytes(), "source name table")?;
+        }
+
+        write_u32(w, self.source_map.iter().count(), "line info")?;
+        for (range, line_info) in self.source_map.iter() {
+            write_u32(w, range.start, "line info")?;
+            write_u32(w, range.end, "line info")?;
+            write_u32(w, line_info.source.index(), "line info")?;
+            write_u32(w, line_info.line, "line info")?;
+            write_u32(w, line_info.column, "line info")?;
+            write_u32(w, line_info.end_line, "line info")?;
+            write_u32(w, line_info.end_column, "line info")?;
+        }
+        Ok(())
+    }
+}
+
+impl Chunk {
+    /// Reads a chunk written by `Chunk::write_to`, interning its string
+    /// constants into the given interner. Constants keep their indices even
+    /// if the file has duplicates.
+    pub fn read_from<R: Read>(r: &mut R, interner: Interner) -> Result<Self, ChunkFileError> {
+        let magic: [u8; 4] = read_array(r, "header")?;
+        if magic != MAGIC {
+            return Err(ChunkFileError::BadMagic(magic));
+        }
+        let version: u16 = read_array::<2, _>(r, "header")?.bytes_to_num();
+        if version != FORMAT_VERSION {
+            return Err(ChunkFileError::UnsupportedVersion(version));
+        }
+
+        let mut chunk = Chunk::with_interner(interner);
+        for _ in 0..read_u32(r, "constant pool")? {
+            let [tag] = read_array(r, "constant pool")?;
+            let constant = match tag_kind(tag)? {
+                ConstantKind::Null => RTValue::null(),
+                ConstantKind::Number => RTValue::number(f64::from_bits(
+                    read_array::<8, _>(r, "constant pool")?.bytes_to_num(),
+                )),
+                ConstantKind::String => {
+                    let string = read_string(r, "constant pool")?;
+                    RTValue::string(chunk.interner.intern(&string))
+                }
+            };
+            chunk.constants.append(constant);
+        }
+
+        chunk.code = read_bytes(r, "code")?;
+
+        let mut sources = vec![];
+        for _ in 0..read_u32(r, "source name table")? {
+            sources.push(chunk.add_source(&read_string(r, "source name table")?));
+        }
+
+        for _ in 0..read_u32(r, "line info")? {
+            let start = read_u32(r, "line info")?;
+            let end = read_u32(r, "line info")?;
+            let name_index = read_u32(r, "line info")?;
+            let line = read_u32(r, "line info")?;
+            let column = read_u32(r, "line info")?;
+            let end_line = read_u32(r, "line info")?;
+            let end_column = read_u32(r, "line info")?;
+            if start >= end || end > chunk.code.len() {
+                return Err(ChunkFileError::BadLineInfo(format!(
+                    "range {}..{} does not fit in {} code bytes",
+                    start,
+                    end,
+                    chunk.code.len()
+                )));
+            }
+            let source = *sources.get(name_index).ok_or_else(|| {
+                ChunkFileError::BadLineInfo(format!("unknown source name index {}", name_index))
+            })?;
+            chunk.source_map.set_range_line_info(
+                start..end,
+                LineInfo::new(source, line, column).with_end(end_line, end_column),
+            );
+        }
+        Ok(chunk)
+    }
+}
+
+fn kind_tag(kind: ConstantKind) -> u8 {
+    match kind {
+        ConstantKind::Null => TAG_NULL,
+        ConstantKind::Number => TAG_NUMBER,
+        ConstantKind::String => TAG_STRING,
+    }
+}
+
+fn tag_kind(tag: u8) -> Result<ConstantKind, ChunkFileError> {
+    match tag {
+        TAG_NULL => Ok(ConstantKind::Null),
+        TAG_NUMBER => Ok(ConstantKind::Number),
+        TAG_STRING => Ok(ConstantKind::String),
+        other => Err(ChunkFileError::BadConstantTag(other)),
+    }
+}
+
+fn write_u32<W: Write>(w: &mut W, n: usize, section: &'static str) -> Result<(), ChunkFileError> {
+    let n = u32::try_from(n).map_err(|_| ChunkFileError::TooLarge(section))?;
+    w.write_all(&ToBytes::<4>::num_to_bytes(&n))?;
+    Ok(())
+}
+
+fn write_bytes<W: Write>(
+    w: &mut W,
+    bytes: &[u8],
+    section: &'static str,
+) -> Result<(), ChunkFileError> {
+    write_u32(w, bytes.len(), section)?;
+    w.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_array<const N: usize, R: Read>(
+    r: &mut R,
+    section: &'static str,
+) -> Result<[u8; N], ChunkFileError> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => ChunkFileError::Truncated(section),
+        _ => ChunkFileError::Io(err),
+    })?;
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(r: &mut R, section: &'static str) -> Result<usize, ChunkFileError> {
+    let n: u32 = read_array::<4, _>(r, section)?.bytes_to_num();
+    Ok(n as usize)
+}
+
+fn read_bytes<R: Read>(r: &mut R, section: &'static str) -> Result<Vec<u8>, ChunkFileError> {
+    let len = read_u32(r, section)?;
+    // Don't trust the length enough to preallocate it.
+    let mut bytes = vec![];
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(ChunkFileError::Truncated(section));
+    }
+    Ok(bytes)
+}
+
+fn read_string<R: Read>(r: &mut R, section: &'static str) -> Result<String, ChunkFileError> {
+    String::from_utf8(read_bytes(r, section)?).map_err(|_| ChunkFileError::BadUtf8(section))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        bytecode::{Chunk, LineInfo, OpCode},
+        interner::Interner,
+        value::RTValue,
+    };
+
+    use super::{ChunkFileError, FORMAT_VERSION};
+
+    fn sample_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        let a = chunk.add_source("a.lox");
+        let b = chunk.add_source("b.lox");
+        chunk.push_constant(RTValue::null());
+        chunk.push_constant_and_load_op(
+            RTValue::number(-0.5),
+            Some(LineInfo::new(a, 1, 2).with_end(1, 6)),
+        );
+        let name = chunk.push_string_constant("größe");
+        chunk.push_load_constant_op(name, Some(LineInfo::new(a, 2, 0)));
+        for n in 0..300 {
+            chunk.push_constant(RTValue::number(n as f64));
+        }
+        chunk.push_load_constant_op(299, Some(LineInfo::new(b, 3, 4).with_end(4, 1)));
+        chunk.push_op_code(OpCode::Return, None);
+        chunk
+    }
+
+    fn line_infos(chunk: &Chunk) -> Vec<Option<&LineInfo>> {
+        (0..chunk.code().len())
+            .map(|offset| chunk.get_line_info(offset))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let chunk = sample_chunk();
+        let mut buf = vec![];
+        chunk.write_to(&mut buf).unwrap();
+        let interner = Interner::new();
+        interner.intern("unrelated");
+        let read = Chunk::read_from(&mut buf.as_slice(), interner.clone()).unwrap();
+        assert_eq!(read.code(), chunk.code());
+        assert_eq!(read.describe_to_string(), chunk.describe_to_string());
+        assert_eq!(line_infos(&read), line_infos(&chunk));
+        assert_eq!(read.source_map().files(), chunk.source_map().files());
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mut buf = vec![];
+        sample_chunk().write_to(&mut buf).unwrap();
+        for len in 0..buf.len() {
+            match Chunk::read_from(&mut &buf[..len], Interner::new()) {
+                Err(ChunkFileError::Truncated(_)) => {}
+                other => panic!(
+                    "Expected truncation error at {}, got {:?}",
+                    len,
+                    other.err()
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut buf = vec![];
+        sample_chunk().write_to(&mut buf).unwrap();
+        let mut wrong_version = buf.clone();
+        wrong_version[5] = (FORMAT_VERSION + 1) as u8;
+        let err = Chunk::read_from(&mut wrong_version.as_slice(), Interner::new())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Unsupported chunk format version {} (expected {})",
+                FORMAT_VERSION + 1,
+                FORMAT_VERSION
+            )
+        );
+        let mut wrong_magic = buf;
+        wrong_magic[0] = b'X';
+        assert!(matches!(
+            Chunk::read_from(&mut wrong_magic.as_slice(), Interner::new()),
+            Err(ChunkFileError::BadMagic(_))
+        ));
+    }
+}
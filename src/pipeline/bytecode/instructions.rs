@@ -0,0 +1,88 @@
+use super::{Chunk, DecodeError, Instruction, LineInfo};
+
+impl Chunk {
+    /// Decodes the instruction starting at `offset`.
+    pub fn instruction_at(&self, offset: usize) -> Option<Result<Instruction, DecodeError>> {
+        self.code
+            .get(offset..)
+            .filter(|code| !code.is_empty())
+            .map(Instruction::decode)
+    }
+
+    /// Iterates over the decoded instructions with their offsets. After an
+    /// unknown opcode, decoding resumes at the next byte; missing operands can
+    /// only happen at the end of the code.
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            chunk: self,
+            offset: 0,
+        }
+    }
+
+    pub fn push_instruction(&mut self, instruction: Instruction, line_info: Option<LineInfo>) {
+        let start = self.code.len();
+        instruction.encode(&mut self.code);
+        if let Some(line_info) = line_info {
+            self.source_map
+                .set_range_line_info(start..self.code.len(), line_info);
+        }
+    }
+}
+
+pub struct Instructions<'c> {
+    chunk: &'c Chunk,
+    offset: usize,
+}
+
+impl<'c> Iterator for Instructions<'c> {
+    type Item = (usize, Result<Instruction, DecodeError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let decoded = self.chunk.instruction_at(offset)?;
+        self.offset += match &decoded {
+            Ok(instruction) => instruction.length(),
+            Err(DecodeError::UnknownOpCode(_)) => 1,
+            Err(DecodeError::MissingOperands { found, .. }) => 1 + found,
+        };
+        Some((offset, decoded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        bytecode::{Chunk, DecodeError, Instruction, OpCode},
+        value::RTValue,
+    };
+
+    #[test]
+    fn decodes_instructions() {
+        let mut chunk = Chunk::new();
+        chunk.push_constant_and_load_op(RTValue::number(1.0), None);
+        chunk.push_instruction(Instruction::ConstantLong(0), None);
+        chunk.push_op_arg(99, None);
+        chunk.push_op_code(OpCode::Return, None);
+        chunk.push_op_code(OpCode::ConstantLong, None);
+        chunk.push_op_arg(0, None);
+        assert_eq!(
+            chunk.instructions().collect::<Vec<_>>(),
+            vec![
+                (0, Ok(Instruction::Constant(0))),
+                (2, Ok(Instruction::ConstantLong(0))),
+                (6, Err(DecodeError::UnknownOpCode(99))),
+                (7, Ok(Instruction::Return)),
+                (
+                    8,
+                    Err(DecodeError::MissingOperands {
+                        op: OpCode::ConstantLong,
+                        expected: 3,
+                        found: 1
+                    })
+                ),
+            ]
+        );
+        assert_eq!(chunk.instruction_at(7), Some(Ok(Instruction::Return)));
+        assert_eq!(chunk.instruction_at(10), None);
+    }
+}
@@ -1,33 +1,382 @@
-use std::mem;
+use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug, Copy, Clone)]
-#[repr(u8)]
-pub enum OpCode {
-    Return = 0,
-    Constant = 1,
-    ConstantLong = 2,
+use super::{
+    bytes::{FromBytes, ToBytes},
+    ConstantIndex, CONSTANT_LONG_ARG_BYTES, JUMP_ARG_BYTES,
+};
+
+/// Static facts about an opcode, shared by the VM, the verifier and the
+/// disassembler.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OpInfo {
+    pub mnemonic: &'static str,
+    pub operand: Option<Operand>,
+    pub pops: usize,
+    pub pushes: usize,
+    pub falls_through: bool,
+}
+
+impl OpInfo {
+    /// Length of the instruction in bytes, including the opcode itself.
+    pub fn length(&self) -> usize {
+        1 + self.operand.as_ref().map_or(0, |operand| operand.width)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Operand {
+    pub kind: OperandKind,
+    pub width: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum OperandKind {
+    Constant,
+    /// Distance to jump forward, counted from the end of the instruction.
+    JumpForward,
+    /// Distance to jump backward, counted from the end of the instruction.
+    JumpBackward,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpCode(u8),
+    MissingOperands {
+        op: OpCode,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpCode(byte) => write!(f, "Unknown op {}", byte),
+            DecodeError::MissingOperands {
+                op,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{:?} expects {} operand bytes, found {}",
+                op, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+macro_rules! operand_type {
+    (Constant) => {
+        ConstantIndex
+    };
+    (JumpForward) => {
+        usize
+    };
+    (JumpBackward) => {
+        usize
+    };
+}
+
+macro_rules! operand_info {
+    () => {
+        None
+    };
+    ($kind:ident, $width:expr) => {
+        Some(Operand {
+            kind: OperandKind::$kind,
+            width: $width,
+        })
+    };
+}
+
+macro_rules! build_instruction {
+    ($operand:ident, $name:ident) => {
+        match $operand {
+            None => Some(Instruction::$name),
+            Some(_) => None,
+        }
+    };
+    ($operand:ident, $name:ident, $kind:ident) => {
+        $operand.map(Instruction::$name)
+    };
+}
+
+macro_rules! second {
+    ($ignored:tt, $kept:tt) => {
+        $kept
+    };
+}
+
+/// Defines every opcode in one place: the `OpCode` enum, its `OpInfo`, the
+/// typed `Instruction` and how instructions are encoded and decoded.
+/// Opcodes are numbered in the order they are listed, so new opcodes go at
+/// the end to keep compiled chunks loadable.
+macro_rules! define_opcodes {
+    ($(
+        $name:ident $(($operand:ident, $width:expr))? {
+            pops: $pops:expr,
+            pushes: $pushes:expr,
+            falls_through: $falls_through:expr $(,)?
+        }
+    ),* $(,)?) => {
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        #[repr(u8)]
+        pub enum OpCode {
+            $($name,)*
+        }
+
+        const OPCODES: &[OpCode] = &[$(OpCode::$name,)*];
+
+        const OP_INFO: &[OpInfo] = &[$(
+            OpInfo {
+                mnemonic: stringify!($name),
+                operand: operand_info!($($operand, $width)?),
+                pops: $pops,
+                pushes: $pushes,
+                falls_through: $falls_through,
+            },
+        )*];
+
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        pub enum Instruction {
+            $($name $((operand_type!($operand)))?,)*
+        }
+
+        impl Instruction {
+            pub fn op_code(&self) -> OpCode {
+                match self {
+                    $(Instruction::$name $((second!($operand, _)))? => OpCode::$name,)*
+                }
+            }
+
+            pub fn operand(&self) -> Option<usize> {
+                match self {
+                    $(Instruction::$name $((second!($operand, operand)))? => {
+                        None $(.or(Some(*second!($operand, operand))))?
+                    })*
+                }
+            }
+
+            /// Decodes the instruction at the start of `code`, which must not
+            /// be empty.
+            pub fn decode(code: &[u8]) -> Result<Instruction, DecodeError> {
+                let op = OpCode::try_from(code[0]).map_err(DecodeError::UnknownOpCode)?;
+                match op {
+                    $(OpCode::$name => Ok(Instruction::$name $((
+                        read_operand::<{ $width }>(op, &code[1..])?
+                    ))?),)*
+                }
+            }
+
+            pub fn encode(&self, code: &mut Vec<u8>) {
+                match self {
+                    $(Instruction::$name $((second!($operand, operand)))? => {
+                        code.push(OpCode::$name as u8);
+                        $(code.extend(ToBytes::<{ $width }>::num_to_bytes(second!($operand, operand)));)?
+                    })*
+                }
+            }
+
+            /// Builds an instruction from its opcode and operand, if the
+            /// operand is present exactly when the opcode takes one.
+            pub fn new(op: OpCode, operand: Option<usize>) -> Option<Instruction> {
+                match op {
+                    $(OpCode::$name => build_instruction!(operand, $name $(, $operand)?),)*
+                }
+            }
+        }
+    };
+}
+
+define_opcodes! {
+    Return {
+        pops: 1,
+        pushes: 0,
+        falls_through: false,
+    },
+    Constant(Constant, 1) {
+        pops: 0,
+        pushes: 1,
+        falls_through: true,
+    },
+    ConstantLong(Constant, CONSTANT_LONG_ARG_BYTES) {
+        pops: 0,
+        pushes: 1,
+        falls_through: true,
+    },
+    Pop {
+        pops: 1,
+        pushes: 0,
+        falls_through: true,
+    },
+    Jump(JumpForward, JUMP_ARG_BYTES) {
+        pops: 0,
+        pushes: 0,
+        falls_through: false,
+    },
+    // Leaves the condition on the stack, like clox.
+    JumpIfFalse(JumpForward, JUMP_ARG_BYTES) {
+        pops: 1,
+        pushes: 1,
+        falls_through: true,
+    },
+    Loop(JumpBackward, JUMP_ARG_BYTES) {
+        pops: 0,
+        pushes: 0,
+        falls_through: false,
+    },
+    Negate {
+        pops: 1,
+        pushes: 1,
+        falls_through: true,
+    },
+}
+
+pub const MAX_OPCODE: u8 = (OPCODES.len() - 1) as u8;
+
+impl OpCode {
+    pub fn info(self) -> &'static OpInfo {
+        &OP_INFO[self as usize]
+    }
+
+    pub fn all() -> &'static [OpCode] {
+        OPCODES
+    }
+
+    /// Converts a byte that is known to be an opcode, such as the first
+    /// byte of an instruction in a chunk that passed `Chunk::verify`.
+    ///
+    /// # Safety
+    ///
+    /// `byte` must be at most `MAX_OPCODE`.
+    #[inline(always)]
+    pub unsafe fn from_u8_unchecked(byte: u8) -> Self {
+        debug_assert!(byte <= MAX_OPCODE);
+        // SAFETY: `OpCode` is `repr(u8)` and numbered from 0 to
+        // `MAX_OPCODE` without gaps.
+        unsafe { std::mem::transmute::<u8, OpCode>(byte) }
+    }
 }
-const MAX_OPCODE: u8 = 2;
 
 impl TryFrom<u8> for OpCode {
     type Error = u8;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if value > MAX_OPCODE {
-            Err(value)
-        } else {
-            Ok(unsafe { mem::transmute(value) })
+        OPCODES.get(value as usize).copied().ok_or(value)
+    }
+}
+
+impl Instruction {
+    pub fn info(&self) -> &'static OpInfo {
+        self.op_code().info()
+    }
+
+    pub fn length(&self) -> usize {
+        self.info().length()
+    }
+
+    /// Where a jump at `offset` continues, or `None` if this isn't a jump or
+    /// the target would be before the start of the code.
+    pub fn jump_target(&self, offset: usize) -> Option<usize> {
+        let next = offset + self.length();
+        match (&self.info().operand, self.operand()) {
+            (
+                Some(Operand {
+                    kind: OperandKind::JumpForward,
+                    ..
+                }),
+                Some(distance),
+            ) => Some(next + distance),
+            (
+                Some(Operand {
+                    kind: OperandKind::JumpBackward,
+                    ..
+                }),
+                Some(distance),
+            ) => next.checked_sub(distance),
+            _ => None,
         }
     }
 }
 
+fn read_operand<const N: usize>(op: OpCode, bytes: &[u8]) -> Result<usize, DecodeError>
+where
+    [u8; N]: FromBytes<usize>,
+{
+    match bytes.get(..N) {
+        Some(bytes) => Ok(<[u8; N]>::try_from(bytes).unwrap().bytes_to_num()),
+        None => Err(DecodeError::MissingOperands {
+            op,
+            expected: N,
+            found: bytes.len(),
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::mem;
 
-    use super::OpCode;
+    use super::{DecodeError, Instruction, OpCode, MAX_OPCODE};
     #[test]
     fn opcode_size() {
         assert_eq!(mem::size_of::<OpCode>(), 1)
     }
+
+    #[test]
+    fn opcode_table() {
+        for (byte, op) in OpCode::all().iter().enumerate() {
+            assert_eq!(*op as usize, byte);
+            assert_eq!(OpCode::try_from(byte as u8), Ok(*op));
+            assert_eq!(op.info().mnemonic, format!("{:?}", op));
+        }
+        assert_eq!(MAX_OPCODE as usize, OpCode::all().len() - 1);
+        assert_eq!(OpCode::try_from(MAX_OPCODE + 1), Err(MAX_OPCODE + 1));
+    }
+
+    #[test]
+    fn encode_decode() {
+        for instruction in [
+            Instruction::Return,
+            Instruction::Constant(255),
+            Instruction::ConstantLong(0x12_3456),
+            Instruction::Pop,
+            Instruction::Negate,
+            Instruction::Jump(0xffff),
+            Instruction::JumpIfFalse(1),
+            Instruction::Loop(0x1234),
+        ] {
+            let mut code = vec![];
+            instruction.encode(&mut code);
+            assert_eq!(code.len(), instruction.length());
+            assert_eq!(Instruction::decode(&code), Ok(instruction));
+        }
+        assert_eq!(
+            Instruction::decode(&[OpCode::ConstantLong as u8, 1]),
+            Err(DecodeError::MissingOperands {
+                op: OpCode::ConstantLong,
+                expected: 3,
+                found: 1
+            })
+        );
+        assert_eq!(Instruction::ConstantLong(7).operand(), Some(7));
+        assert_eq!(
+            Instruction::new(OpCode::Constant, Some(7)),
+            Some(Instruction::Constant(7))
+        );
+        assert_eq!(Instruction::new(OpCode::Constant, None), None);
+        assert_eq!(Instruction::new(OpCode::Return, Some(1)), None);
+        assert_eq!(Instruction::Return.operand(), None);
+    }
+
+    #[test]
+    fn jump_targets() {
+        assert_eq!(Instruction::Jump(5).jump_target(10), Some(18));
+        assert_eq!(Instruction::JumpIfFalse(0).jump_target(0), Some(3));
+        assert_eq!(Instruction::Loop(13).jump_target(10), Some(0));
+        assert_eq!(Instruction::Loop(14).jump_target(10), None);
+        assert_eq!(Instruction::Constant(1).jump_target(10), None);
+    }
 }
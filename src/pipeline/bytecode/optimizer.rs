@@ -0,0 +1,522 @@
+//! Peephole optimizations over finished chunks. The code is decoded into a
+//! list of instructions whose jumps point at other entries of the list, the
+//! list is rewritten until nothing changes, and the result is encoded again
+//! with new jump distances and the surviving instructions' line info.
+//!
+//! The rewrites are:
+//!
+//! - `Constant x; Negate` becomes `Constant -x` for numbers.
+//! - `Constant x; Pop` is dropped.
+//! - Jumps to jumps go straight to the final target, and a `JumpIfFalse`
+//!   landing on another `JumpIfFalse` takes that one's target too, since the
+//!   condition is still on the stack.
+//! - Jumps to the next instruction are dropped.
+//! - Code that no path reaches is dropped.
+//!
+//! Pairs are only combined when no jump lands between them. Afterwards the
+//! constant pool keeps only the constants the remaining code loads.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::pipeline::value::RTValue;
+
+use super::{Chunk, ConstantIndex, ConstantPool, Instruction, LineInfo, MAX_CONSTANTS, MAX_JUMP};
+
+struct Item {
+    instruction: Instruction,
+    line_info: Option<LineInfo>,
+    /// Index of the item a jump goes to; `items.len()` is the end of the
+    /// code.
+    target: Option<usize>,
+    removed: bool,
+}
+
+impl Chunk {
+    /// Rewrites the code to do the same work in fewer instructions. Chunks
+    /// that don't pass `verify` are left alone, as are chunks whose
+    /// rewritten jumps would no longer fit their operands.
+    pub fn optimize(&mut self) {
+        if self.verify().is_err() {
+            return;
+        }
+        let Some(mut items) = self.decode_items() else {
+            return;
+        };
+        let mut added = ConstantPool::new();
+        while self.rewrite(&mut items, &mut added) {}
+        let constants = self.compact_constants(&mut items, &added);
+        if let Some(encoded) = encode(&items) {
+            self.constants = constants;
+            self.code.clear();
+            self.source_map.clear_line_info();
+            for (instruction, line_info) in encoded {
+                self.push_instruction(instruction, line_info);
+            }
+        }
+    }
+
+    fn decode_items(&self) -> Option<Vec<Item>> {
+        let decoded: Vec<(usize, Instruction)> = self
+            .instructions()
+            .map(|(offset, decoded)| Some((offset, decoded.ok()?)))
+            .collect::<Option<_>>()?;
+        let mut indexes: HashMap<usize, usize> = decoded
+            .iter()
+            .enumerate()
+            .map(|(index, (offset, _))| (*offset, index))
+            .collect();
+        indexes.insert(self.code.len(), decoded.len());
+        decoded
+            .iter()
+            .map(|(offset, instruction)| {
+                let target = match instruction.jump_target(*offset) {
+                    Some(target) => Some(*indexes.get(&target)?),
+                    None => None,
+                };
+                Some(Item {
+                    instruction: *instruction,
+                    line_info: self.get_line_info(*offset).copied(),
+                    target,
+                    removed: false,
+                })
+            })
+            .collect()
+    }
+
+    /// Applies each rewrite once and reports whether anything changed.
+    /// Constants the rewrites need are put in `added`, numbered after the
+    /// chunk's own, and only reach the chunk if the result is encoded.
+    fn rewrite(&self, items: &mut [Item], added: &mut ConstantPool) -> bool {
+        let mut changed = remove_unreachable(items);
+
+        for index in 0..items.len() {
+            let Some(target) = items[index].target else {
+                continue;
+            };
+            let threaded = thread_jump(items, index, target);
+            if threaded != target {
+                items[index].target = Some(threaded);
+                changed = true;
+            }
+        }
+
+        let targets: HashSet<usize> = items
+            .iter()
+            .filter(|item| !item.removed)
+            .filter_map(|item| item.target)
+            .map(|target| live_from(items, target))
+            .collect();
+        let live: Vec<usize> = (0..items.len()).filter(|i| !items[*i].removed).collect();
+        for pair in live.windows(2) {
+            let (first, second) = (pair[0], pair[1]);
+            if items[first].removed || items[second].removed || targets.contains(&second) {
+                continue;
+            }
+            let index = match items[first].instruction {
+                Instruction::Constant(index) | Instruction::ConstantLong(index) => index,
+                _ => continue,
+            };
+            match items[second].instruction {
+                Instruction::Pop => {
+                    items[first].removed = true;
+                    items[second].removed = true;
+                    changed = true;
+                }
+                Instruction::Negate => {
+                    let Some(number) = self
+                        .constant_or_added(added, index)
+                        .and_then(RTValue::as_number)
+                    else {
+                        continue;
+                    };
+                    let negated = RTValue::number(-number);
+                    let existing = self.constants.index_of(&negated);
+                    let negated = match existing.or_else(|| {
+                        let index = added.index_of(&negated)?;
+                        Some(self.constants.len() + index)
+                    }) {
+                        Some(index) => index,
+                        None if self.constants.len() + added.len() < MAX_CONSTANTS => {
+                            self.constants.len() + added.insert(negated)
+                        }
+                        None => continue,
+                    };
+                    items[first].instruction = load_constant(negated);
+                    items[first].line_info = join(items[first].line_info, items[second].line_info);
+                    items[second].removed = true;
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        for index in 0..items.len() {
+            if items[index].removed {
+                continue;
+            }
+            if let (Instruction::Jump(_) | Instruction::JumpIfFalse(_), Some(target)) =
+                (items[index].instruction, items[index].target)
+            {
+                if live_from(items, target) == live_from(items, index + 1) {
+                    items[index].removed = true;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Renumbers the constants that surviving items load, keeping their
+    /// order, and returns a pool of just those.
+    fn compact_constants(&self, items: &mut [Item], added: &ConstantPool) -> ConstantPool {
+        let mut used: Vec<ConstantIndex> = items
+            .iter()
+            .filter(|item| !item.removed)
+            .filter_map(|item| constant_index(item.instruction))
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+        let mut pool = ConstantPool::new();
+        let renumbered: HashMap<ConstantIndex, ConstantIndex> = used
+            .into_iter()
+            .map(|index| {
+                let value = *self
+                    .constant_or_added(added, index)
+                    .expect("verified chunk");
+                (index, pool.append(value))
+            })
+            .collect();
+        for item in items.iter_mut().filter(|item| !item.removed) {
+            if let Some(index) = constant_index(item.instruction) {
+                item.instruction = load_constant(renumbered[&index]);
+            }
+        }
+        pool
+    }
+
+    fn constant_or_added<'a>(
+        &'a self,
+        added: &'a ConstantPool,
+        index: ConstantIndex,
+    ) -> Option<&'a RTValue> {
+        match index.checked_sub(self.constants.len()) {
+            Some(index) => added.get(index),
+            None => self.get_constant(index),
+        }
+    }
+}
+
+fn constant_index(instruction: Instruction) -> Option<ConstantIndex> {
+    match instruction {
+        Instruction::Constant(index) | Instruction::ConstantLong(index) => Some(index),
+        _ => None,
+    }
+}
+
+/// The first item at or after `index` that hasn't been removed, or
+/// `items.len()` for the end of the code.
+fn live_from(items: &[Item], index: usize) -> usize {
+    (index..items.len())
+        .find(|index| !items[*index].removed)
+        .unwrap_or(items.len())
+}
+
+/// Follows a jump through the jumps it lands on. Only jumps that keep going
+/// the same direction are followed, so forward jumps stay forward jumps.
+fn thread_jump(items: &[Item], jump: usize, target: usize) -> usize {
+    let forward = !matches!(items[jump].instruction, Instruction::Loop(_));
+    let mut current = target;
+    for _ in 0..items.len() {
+        let landing = live_from(items, current);
+        let Some(item) = items.get(landing) else {
+            return current;
+        };
+        let follows = matches!(
+            (items[jump].instruction, item.instruction),
+            (_, Instruction::Jump(_)) | (Instruction::JumpIfFalse(_), Instruction::JumpIfFalse(_))
+        );
+        match item.target.filter(|_| follows) {
+            Some(next) if (next > jump) == forward && next != landing => current = next,
+            _ => return current,
+        }
+    }
+    // Jumps that only reach each other loop forever; leave them be.
+    target
+}
+
+/// Marks items that no path from the start reaches as removed, and reports
+/// whether there were any.
+fn remove_unreachable(items: &mut [Item]) -> bool {
+    let mut reached = vec![false; items.len()];
+    let mut pending = vec![live_from(items, 0)];
+    while let Some(index) = pending.pop() {
+        let index = live_from(items, index);
+        if index >= items.len() || reached[index] {
+            continue;
+        }
+        reached[index] = true;
+        if items[index].instruction.info().falls_through {
+            pending.push(index + 1);
+        }
+        if let Some(target) = items[index].target {
+            pending.push(target);
+        }
+    }
+    let mut changed = false;
+    for (item, reached) in items.iter_mut().zip(reached) {
+        if !item.removed && !reached {
+            item.removed = true;
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn load_constant(index: usize) -> Instruction {
+    if index <= u8::MAX as usize {
+        Instruction::Constant(index)
+    } else {
+        Instruction::ConstantLong(index)
+    }
+}
+
+/// Widens `first` to end where `second` ends, if both are in the same source.
+fn join(first: Option<LineInfo>, second: Option<LineInfo>) -> Option<LineInfo> {
+    match (first, second) {
+        (Some(first), Some(second)) if first.source == second.source => {
+            Some(first.with_end(second.end_line, second.end_column))
+        }
+        (first, _) => first,
+    }
+}
+
+/// Lays out the surviving items and fills in their jump distances, or gives
+/// up if a jump no longer fits.
+fn encode(items: &[Item]) -> Option<Vec<(Instruction, Option<LineInfo>)>> {
+    let mut offsets = vec![0; items.len() + 1];
+    let mut offset = 0;
+    for (index, item) in items.iter().enumerate() {
+        offsets[index] = offset;
+        if !item.removed {
+            offset += item.instruction.length();
+        }
+    }
+    offsets[items.len()] = offset;
+
+    items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !item.removed)
+        .map(|(index, item)| {
+            let instruction = match item.target {
+                None => item.instruction,
+                Some(target) => {
+                    let next = offsets[index] + item.instruction.length();
+                    let target = offsets[live_from(items, target)];
+                    let distance = match item.instruction {
+                        Instruction::Loop(_) => next.checked_sub(target)?,
+                        _ => target.checked_sub(next)?,
+                    };
+                    if distance > MAX_JUMP {
+                        return None;
+                    }
+                    Instruction::new(item.instruction.op_code(), Some(distance))?
+                }
+            };
+            Some((instruction, item.line_info))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::{self, Write},
+        rc::Rc,
+    };
+
+    use crate::pipeline::{
+        bytecode::{Chunk, MAX_JUMP},
+        vm::VM,
+    };
+
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(chunk: &Chunk) -> String {
+        let output = Output::default();
+        VM::new()
+            .with_output(Box::new(output.clone()))
+            .with_chunk(chunk)
+            .run()
+            .unwrap();
+        let bytes = output.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Optimizes the listing and checks the result's disassembly (after the
+    /// `.file` line listings with line numbers get) and that it prints the
+    /// same as the original.
+    fn assert_optimizes(listing: &str, expected: &str) {
+        let original = Chunk::assemble(listing, "test").unwrap();
+        let mut optimized = Chunk::assemble(listing, "test").unwrap();
+        optimized.optimize();
+        let description = optimized.describe_to_string();
+        let code = description.strip_prefix(".file \"test\"\n");
+        assert_eq!(code.unwrap_or(&description), expected);
+        optimized.verify().unwrap();
+        assert_eq!(run(&optimized), run(&original));
+    }
+
+    #[test]
+    fn folds_negated_constants() {
+        assert_optimizes(
+            "; line 1\n\
+             Constant 1.5\n\
+             Negate\n\
+             Negate\n\
+             ; line 2\n\
+             Return",
+            "0000    1 Constant    0 Number(1.5)\n\
+             0002    2 Return\n",
+        );
+    }
+
+    #[test]
+    fn folds_into_existing_constants_and_compacts_the_pool() {
+        let mut chunk = Chunk::assemble("Constant 1.5\nNegate\nNegate\nReturn", "test").unwrap();
+        chunk.optimize();
+        assert_eq!(chunk.constants().len(), 1);
+
+        let mut listing = String::new();
+        for n in 0..300 {
+            listing.push_str(&format!("ConstantLong {}\nPop\n", n));
+        }
+        listing.push_str("ConstantLong 299\nReturn");
+        assert_optimizes(
+            &listing,
+            "0000    ? Constant    0 Number(299.0)\n0002    ? Return\n",
+        );
+        let mut chunk = Chunk::assemble(&listing, "test").unwrap();
+        chunk.optimize();
+        assert_eq!(chunk.constants().len(), 1);
+    }
+
+    #[test]
+    fn drops_unused_constants() {
+        assert_optimizes(
+            "; line 1\n\
+             Constant 1\n\
+             Constant 2\n\
+             Pop\n\
+             Constant nil\n\
+             Pop\n\
+             Return",
+            "0000    1 Constant    0 Number(1.0)\n\
+             0002    | Return\n",
+        );
+    }
+
+    #[test]
+    fn threads_jumps() {
+        assert_optimizes(
+            "; line 1\n\
+             Constant nil\n\
+             JumpIfFalse middle\n\
+             Pop\n\
+             Constant 1\n\
+             middle:\n\
+             Jump end\n\
+             Constant 2\n\
+             end:\n\
+             ; line 2\n\
+             Return",
+            "0000    1 Constant    0 Null\n\
+             0002    | JumpIfFalse    3 -> 0008\n\
+             0005    | Pop\n\
+             0006    | Constant    1 Number(1.0)\n\
+             0008    2 Return\n",
+        );
+    }
+
+    #[test]
+    fn removes_jumps_that_go_nowhere() {
+        assert_optimizes(
+            "; line 1\n\
+             Constant nil\n\
+             JumpIfFalse first\n\
+             Constant 1\n\
+             Pop\n\
+             first:\n\
+             JumpIfFalse second\n\
+             Jump end\n\
+             second:\n\
+             Jump end\n\
+             end:\n\
+             ; line 2\n\
+             Return",
+            "0000    1 Constant    0 Null\n\
+             0002    2 Return\n",
+        );
+    }
+
+    #[test]
+    fn keeps_pairs_split_by_jump_targets() {
+        assert_optimizes(
+            "Constant 2\n\
+             Constant 0\n\
+             JumpIfFalse skip\n\
+             Pop\n\
+             Constant 1\n\
+             skip:\n\
+             Negate\n\
+             Return",
+            "0000    ? Constant    0 Number(2.0)\n\
+             0002    ? Constant    1 Number(0.0)\n\
+             0004    ? JumpIfFalse    3 -> 0010\n\
+             0007    ? Pop\n\
+             0008    ? Constant    2 Number(1.0)\n\
+             0010    ? Negate\n\
+             0011    ? Return\n",
+        );
+    }
+
+    #[test]
+    fn leaves_the_pool_alone_when_jumps_no_longer_fit() {
+        // Folding `Constant 1; Negate` adds constant 256, which needs a
+        // ConstantLong and pushes the jump past `MAX_JUMP`.
+        let mut listing = String::from("Constant 1\nJumpIfFalse end\n");
+        for n in 1..=254 {
+            listing += &format!("Constant \"{}\"\nNegate\nPop\n", n);
+        }
+        listing += "Constant 1\nNegate\nConstant Null\n";
+        listing += &"Negate\n".repeat(MAX_JUMP - 1023);
+        listing += "Pop\nPop\nend:\nReturn";
+        let mut chunk = Chunk::assemble(&listing, "test").unwrap();
+        chunk.verify().unwrap();
+        assert_eq!(chunk.constants().len(), 256);
+        let before = chunk.describe_to_string();
+        chunk.optimize();
+        assert_eq!(chunk.constants().len(), 256);
+        assert_eq!(chunk.describe_to_string(), before);
+    }
+
+    #[test]
+    fn leaves_malformed_chunks_alone() {
+        let mut chunk = Chunk::assemble("Constant 1\nPop\nPop", "test").unwrap();
+        let before = chunk.describe_to_string();
+        chunk.optimize();
+        assert_eq!(chunk.describe_to_string(), before);
+    }
+}
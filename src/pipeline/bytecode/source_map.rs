@@ -1,44 +1,145 @@
+use std::ops::Range;
+
 use rangemap::RangeMap;
 
-pub struct SourceMap<'s> {
-    line_info: RangeMap<usize, LineInfo<'s>>,
+/// Maps code offsets to the source spans they were compiled from. Source
+/// names live in a file table owned by the map, so chunks don't borrow from
+/// their sources.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<String>,
+    line_info: RangeMap<usize, LineInfo>,
+}
+
+/// Index of a source name in a `SourceMap`'s file table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(u32);
+
+impl SourceId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
 }
 
-impl<'s> SourceMap<'s> {
+impl SourceMap {
     pub fn new() -> Self {
-        Self {
-            line_info: RangeMap::new(),
-        }
+        Self::default()
+    }
+
+    /// Returns the id of the source called `name`, adding it to the file
+    /// table if needed.
+    pub fn add_file(&mut self, name: &str) -> SourceId {
+        let index = match self.files.iter().position(|file| file == name) {
+            Some(index) => index,
+            None => {
+                self.files.push(name.to_string());
+                self.files.len() - 1
+            }
+        };
+        SourceId(u32::try_from(index).expect("Too many source files"))
+    }
+
+    pub fn file_name(&self, source: SourceId) -> Option<&str> {
+        self.files.get(source.index()).map(String::as_str)
+    }
+
+    /// The id of the `index`th file in the file table, if there is one.
+    pub fn file_id(&self, index: usize) -> Option<SourceId> {
+        (index < self.files.len()).then_some(SourceId(index as u32))
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
     }
 
-    pub fn set_line_info(&mut self, instruction_index: usize, info: LineInfo<'s>) {
-        self.line_info.insert(instruction_index..instruction_index+1, info);
+    pub fn set_line_info(&mut self, instruction_index: usize, info: LineInfo) {
+        self.set_range_line_info(instruction_index..instruction_index + 1, info);
     }
 
-    pub fn get_line_info(&self, instruction_index: usize) -> Option<&LineInfo<'s>> {
+    pub fn get_line_info(&self, instruction_index: usize) -> Option<&LineInfo> {
         self.line_info.get(&instruction_index)
     }
-}
 
-impl<'s> Default for SourceMap<'s> {
-    fn default() -> Self {
-        Self::new()
+    /// Iterates over maximal ranges of code with the same line info.
+    pub fn iter(&self) -> impl Iterator<Item = (&Range<usize>, &LineInfo)> {
+        self.line_info.iter()
+    }
+
+    /// Forgets all line info, keeping the file table.
+    pub fn clear_line_info(&mut self) {
+        self.line_info = RangeMap::new();
+    }
+
+    /// Adjacent ranges with equal line info are merged into one.
+    pub fn set_range_line_info(&mut self, instructions: Range<usize>, info: LineInfo) {
+        self.line_info.insert(instructions, info);
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct LineInfo<'s> {
-    pub source_name: &'s str,
+/// A span of source text, from the start position up to and including the
+/// end position. Lines and columns are as reported by the scanner.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LineInfo {
+    pub source: SourceId,
     pub line: usize,
     pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
 }
 
-impl<'s> LineInfo<'s> {
-    pub fn new(source_name: &'s str, line: usize, column: usize) -> Self {
+impl LineInfo {
+    /// A span covering the single position at `line` and `column`.
+    pub fn new(source: SourceId, line: usize, column: usize) -> Self {
         Self {
-            source_name,
+            source,
             line,
             column,
+            end_line: line,
+            end_column: column,
+        }
+    }
+
+    pub fn with_end(self, end_line: usize, end_column: usize) -> Self {
+        Self {
+            end_line,
+            end_column,
+            ..self
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::bytecode::Chunk;
+
+    use super::{LineInfo, SourceMap};
+
+    #[test]
+    fn chunks_do_not_borrow_sources() {
+        fn is_static<T: 'static>() {}
+        is_static::<Chunk>();
+    }
+
+    #[test]
+    fn files_and_ranges() {
+        let mut map = SourceMap::new();
+        let a = map.add_file("a.lox");
+        let b = map.add_file("b.lox");
+        assert_eq!(map.add_file("a.lox"), a);
+        assert_eq!(map.file_name(b), Some("b.lox"));
+        assert_eq!(map.files(), ["a.lox", "b.lox"]);
+
+        let first = LineInfo::new(a, 1, 0).with_end(1, 4);
+        map.set_range_line_info(0..2, first);
+        map.set_line_info(2, first);
+        map.set_range_line_info(3..6, LineInfo::new(b, 2, 0));
+        assert_eq!(
+            map.iter()
+                .map(|(range, info)| (range.clone(), info.line))
+                .collect::<Vec<_>>(),
+            [(0..3, 1), (3..6, 2)]
+        );
+        assert_eq!(map.get_line_info(2).map(|info| info.end_column), Some(4));
+        assert_eq!(map.get_line_info(6), None);
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::{Chunk, DecodeError, OpCode, Operand, OperandKind};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    offset: usize,
+    kind: VerifyErrorKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    Decode(DecodeError),
+    BadConstantIndex(usize),
+    BadJumpTarget,
+    JumpOutOfRange(usize),
+    StackUnderflow { op: OpCode, depth: usize },
+    InconsistentStackDepth { expected: usize, found: usize },
+}
+
+impl VerifyError {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn kind(&self) -> &VerifyErrorKind {
+        &self.kind
+    }
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:0>4}: ", self.offset)?;
+        match &self.kind {
+            VerifyErrorKind::Decode(err) => write!(f, "{}", err),
+            VerifyErrorKind::BadConstantIndex(index) => {
+                write!(f, "Bad constant index {}", index)
+            }
+            VerifyErrorKind::BadJumpTarget => write!(f, "Jump before the start of the code"),
+            VerifyErrorKind::JumpOutOfRange(target) => {
+                write!(f, "Jump to {:0>4}, past the end of the code", target)
+            }
+            VerifyErrorKind::StackUnderflow { op, depth } => {
+                write!(f, "{:?} would underflow a stack of depth {}", op, depth)
+            }
+            VerifyErrorKind::InconsistentStackDepth { expected, found } => write!(
+                f,
+                "Stack depth {} differs from depth {} on another path",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl Chunk {
+    /// Checks that the bytecode can run without the VM hitting malformed
+    /// instructions: every opcode decodes, operands are complete, constant
+    /// indices exist, jumps stay within the code, and the stack never
+    /// underflows along any path.
+    ///
+    /// Falling off the end of the code, or jumping to just past the last
+    /// instruction, is allowed; the VM stops there.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let mut depths: Vec<Option<usize>> = vec![None; self.code.len()];
+        let mut pending = vec![(0, 0)];
+        while let Some((offset, depth)) = pending.pop() {
+            if offset >= self.code.len() {
+                continue;
+            }
+            match depths[offset] {
+                Some(expected) if expected == depth => continue,
+                Some(expected) => {
+                    return Err(VerifyError {
+                        offset,
+                        kind: VerifyErrorKind::InconsistentStackDepth {
+                            expected,
+                            found: depth,
+                        },
+                    })
+                }
+                None => depths[offset] = Some(depth),
+            }
+            let instruction = self
+                .instruction_at(offset)
+                .expect("offset is within the code")
+                .map_err(|err| VerifyError {
+                    offset,
+                    kind: VerifyErrorKind::Decode(err),
+                })?;
+            let info = instruction.info();
+            if let (
+                Some(Operand {
+                    kind: OperandKind::Constant,
+                    ..
+                }),
+                Some(index),
+            ) = (&info.operand, instruction.operand())
+            {
+                if self.get_constant(index).is_none() {
+                    return Err(VerifyError {
+                        offset,
+                        kind: VerifyErrorKind::BadConstantIndex(index),
+                    });
+                }
+            }
+            if depth < info.pops {
+                return Err(VerifyError {
+                    offset,
+                    kind: VerifyErrorKind::StackUnderflow {
+                        op: instruction.op_code(),
+                        depth,
+                    },
+                });
+            }
+            let depth = depth - info.pops + info.pushes;
+            if info.falls_through {
+                pending.push((offset + info.length(), depth));
+            }
+            if let (Some(OperandKind::JumpForward | OperandKind::JumpBackward), target) = (
+                info.operand.as_ref().map(|operand| &operand.kind),
+                instruction.jump_target(offset),
+            ) {
+                let target = target.ok_or(VerifyError {
+                    offset,
+                    kind: VerifyErrorKind::BadJumpTarget,
+                })?;
+                if target > self.code.len() {
+                    return Err(VerifyError {
+                        offset,
+                        kind: VerifyErrorKind::JumpOutOfRange(target),
+                    });
+                }
+                pending.push((target, depth));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        bytecode::{Chunk, Instruction, OpCode},
+        value::RTValue,
+    };
+
+    use super::{VerifyError, VerifyErrorKind};
+
+    fn verify(build: impl FnOnce(&mut Chunk)) -> Result<(), VerifyError> {
+        let mut chunk = Chunk::new();
+        build(&mut chunk);
+        chunk.verify()
+    }
+
+    #[test]
+    fn accepts_well_formed_chunks() {
+        assert_eq!(verify(|_| {}), Ok(()));
+        assert_eq!(
+            verify(|chunk| chunk.push_instruction(Instruction::Jump(0), None)),
+            Ok(())
+        );
+        assert_eq!(
+            verify(|chunk| {
+                chunk.push_constant_and_load_op(RTValue::number(1.0), None);
+                let jump = chunk.push_jump(OpCode::JumpIfFalse, None);
+                chunk.push_op_code(OpCode::Pop, None);
+                chunk.push_constant_and_load_op(RTValue::null(), None);
+                chunk.patch_jump(jump);
+                chunk.push_op_code(OpCode::Return, None);
+                // Unreachable, so never checked.
+                chunk.push_op_code(OpCode::Return, None);
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_chunks() {
+        let error = |build: fn(&mut Chunk)| {
+            let error = verify(build).unwrap_err();
+            (error.offset(), error.to_string())
+        };
+        assert_eq!(
+            error(|chunk| chunk.push_op_arg(200, None)),
+            (0, "0000: Unknown op 200".to_string())
+        );
+        assert_eq!(
+            error(|chunk| {
+                chunk.push_constant_and_load_op(RTValue::null(), None);
+                chunk.push_op_code(OpCode::ConstantLong, None);
+                chunk.push_op_arg(0, None);
+            }),
+            (
+                2,
+                "0002: ConstantLong expects 3 operand bytes, found 1".to_string()
+            )
+        );
+        assert_eq!(
+            error(|chunk| chunk.push_load_constant_op(3, None)),
+            (0, "0000: Bad constant index 3".to_string())
+        );
+        assert_eq!(
+            error(|chunk| chunk.push_instruction(Instruction::Loop(4), None)),
+            (0, "0000: Jump before the start of the code".to_string())
+        );
+        assert_eq!(
+            error(|chunk| chunk.push_instruction(Instruction::Jump(1), None)),
+            (
+                0,
+                "0000: Jump to 0004, past the end of the code".to_string()
+            )
+        );
+        assert_eq!(
+            error(|chunk| {
+                chunk.push_constant_and_load_op(RTValue::null(), None);
+                let jump = chunk.push_jump(OpCode::JumpIfFalse, None);
+                chunk.push_op_code(OpCode::Pop, None);
+                chunk.patch_jump(jump);
+                chunk.push_op_code(OpCode::Return, None);
+            }),
+            (
+                6,
+                "0006: Stack depth 0 differs from depth 1 on another path".to_string()
+            )
+        );
+        assert_eq!(
+            verify(|chunk| chunk.push_op_code(OpCode::Return, None))
+                .unwrap_err()
+                .kind(),
+            &VerifyErrorKind::StackUnderflow {
+                op: OpCode::Return,
+                depth: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// Handle to an interned string. Symbols from the same `Interner` are equal
+/// exactly when their strings are equal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    /// The symbol with the given index, for value representations that
+    /// store symbols as plain numbers.
+    #[cfg_attr(not(feature = "nan-boxing"), allow(dead_code))]
+    pub(crate) fn from_index(index: u32) -> Self {
+        Symbol(index)
+    }
+}
+
+/// String table shared by the scanner, the compiler, chunks and the VM.
+/// Cloning an `Interner` gives another handle to the same table.
+#[derive(Clone, Default)]
+pub struct Interner {
+    table: Rc<RefCell<Table>>,
+}
+
+#[derive(Default)]
+struct Table {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&self, string: &str) -> Symbol {
+        let mut table = self.table.borrow_mut();
+        if let Some(symbol) = table.symbols.get(string) {
+            return *symbol;
+        }
+        let symbol = Symbol(u32::try_from(table.strings.len()).expect("Too many interned strings"));
+        let string: Rc<str> = Rc::from(string);
+        table.strings.push(string.clone());
+        table.symbols.insert(string, symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> Option<Rc<str>> {
+        self.table.borrow().strings.get(symbol.index()).cloned()
+    }
+
+    /// Every interned string, in the order they were interned.
+    pub fn strings(&self) -> Vec<Rc<str>> {
+        self.table.borrow().strings.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.borrow().strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn shares_table_with(&self, other: &Interner) -> bool {
+        Rc::ptr_eq(&self.table, &other.table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interner;
+
+    #[test]
+    fn intern_and_resolve() {
+        let interner = Interner::new();
+        let a = interner.intern("alpha");
+        let b = interner.intern("beta");
+        assert_ne!(a, b);
+        assert_eq!(interner.clone().intern("alpha"), a);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.resolve(b).as_deref(), Some("beta"));
+        assert_eq!(Interner::new().resolve(b), None);
+    }
+}
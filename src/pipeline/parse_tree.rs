@@ -0,0 +1,106 @@
+//! The syntax tree the parser hands to the compiler. Every node keeps the
+//! position of the token it starts at, so diagnostics about it, and about
+//! whatever a pass rewrites it into, point at the right place.
+
+use super::{interner::Symbol, tokens::TokenMeta};
+
+mod optimizer;
+pub use optimizer::optimize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub meta: TokenMeta,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Grouping(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    Logical(Box<Expr>, LogicalOp, Box<Expr>),
+    Variable(Symbol),
+    Assign(Symbol, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Literal {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Symbol),
+}
+
+impl Literal {
+    /// `nil` and `false` are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Literal::Nil | Literal::Bool(false))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub meta: TokenMeta,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Expression(Expr),
+    Print(Expr),
+    Var(Symbol, Option<Expr>),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    Function(Symbol, Vec<Symbol>, Vec<Stmt>),
+    Return(Option<Expr>),
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, meta: TokenMeta) -> Self {
+        Self { kind, meta }
+    }
+
+    /// The literal this expression is, looking through parentheses.
+    pub fn as_literal(&self) -> Option<Literal> {
+        match &self.kind {
+            ExprKind::Literal(literal) => Some(*literal),
+            ExprKind::Grouping(inner) => inner.as_literal(),
+            _ => None,
+        }
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, meta: TokenMeta) -> Self {
+        Self { kind, meta }
+    }
+}
@@ -0,0 +1,486 @@
+//! Folds what can be worked out before running a program:
+//!
+//! - Arithmetic and comparisons on number literals, equality on any
+//!   literals, concatenation of string literals, `!` and `-` on literals,
+//!   and `and`/`or` whose left side is a literal.
+//! - `if` and `while` statements whose condition is a literal keep only the
+//!   branch that runs.
+//! - Statements after a `return` in the same block are dropped, as are
+//!   expression statements that are just a literal and empty blocks.
+//!
+//! Expressions that would fail at runtime, like `-"text"`, are left for the
+//! runtime to report. A folded node keeps the position of the node it
+//! replaces, except that an `and` or `or` that comes down to its right side
+//! becomes that side, position and all.
+
+use std::mem;
+
+use crate::pipeline::interner::Interner;
+
+use super::{BinaryOp, Expr, ExprKind, Literal, LogicalOp, Stmt, StmtKind, UnaryOp};
+
+/// Optimizes a program's statements in place. New strings from folded
+/// concatenations are interned into `interner`, which should be the one the
+/// program's strings come from.
+pub fn optimize(statements: &mut Vec<Stmt>, interner: &Interner) {
+    fold_block(statements, interner);
+}
+
+fn fold_block(statements: &mut Vec<Stmt>, interner: &Interner) {
+    let mut folded = Vec::with_capacity(statements.len());
+    for statement in mem::take(statements) {
+        let Some(statement) = fold_stmt(statement, interner) else {
+            continue;
+        };
+        let returns = matches!(statement.kind, StmtKind::Return(_));
+        folded.push(statement);
+        if returns {
+            break;
+        }
+    }
+    *statements = folded;
+}
+
+/// Folds a statement, or returns `None` if nothing of it is left to run.
+fn fold_stmt(statement: Stmt, interner: &Interner) -> Option<Stmt> {
+    let Stmt { kind, meta } = statement;
+    let kind = match kind {
+        StmtKind::Expression(mut expr) => {
+            fold_expr(&mut expr, interner);
+            if matches!(expr.kind, ExprKind::Literal(_)) {
+                return None;
+            }
+            StmtKind::Expression(expr)
+        }
+        StmtKind::Print(mut expr) => {
+            fold_expr(&mut expr, interner);
+            StmtKind::Print(expr)
+        }
+        StmtKind::Var(name, mut initializer) => {
+            if let Some(expr) = &mut initializer {
+                fold_expr(expr, interner);
+            }
+            StmtKind::Var(name, initializer)
+        }
+        StmtKind::Block(mut statements) => {
+            fold_block(&mut statements, interner);
+            if statements.is_empty() {
+                return None;
+            }
+            StmtKind::Block(statements)
+        }
+        StmtKind::If(mut condition, then_branch, else_branch) => {
+            fold_expr(&mut condition, interner);
+            if let Some(literal) = condition.as_literal() {
+                let branch = if literal.is_truthy() {
+                    Some(then_branch)
+                } else {
+                    else_branch
+                };
+                return fold_stmt(*branch?, interner);
+            }
+            let else_branch = else_branch.and_then(|branch| fold_stmt(*branch, interner));
+            StmtKind::If(
+                condition,
+                Box::new(fold_body(*then_branch, interner)),
+                else_branch.map(Box::new),
+            )
+        }
+        StmtKind::While(mut condition, body) => {
+            fold_expr(&mut condition, interner);
+            if condition
+                .as_literal()
+                .is_some_and(|literal| !literal.is_truthy())
+            {
+                return None;
+            }
+            StmtKind::While(condition, Box::new(fold_body(*body, interner)))
+        }
+        StmtKind::Function(name, parameters, mut body) => {
+            fold_block(&mut body, interner);
+            StmtKind::Function(name, parameters, body)
+        }
+        StmtKind::Return(mut value) => {
+            if let Some(expr) = &mut value {
+                fold_expr(expr, interner);
+            }
+            StmtKind::Return(value)
+        }
+    };
+    Some(Stmt::new(kind, meta))
+}
+
+/// Folds the body of an `if` or `while`, which has to stay a statement.
+fn fold_body(body: Stmt, interner: &Interner) -> Stmt {
+    let meta = body.meta;
+    fold_stmt(body, interner).unwrap_or_else(|| Stmt::new(StmtKind::Block(Vec::new()), meta))
+}
+
+fn fold_expr(expr: &mut Expr, interner: &Interner) {
+    let mut meta = expr.meta;
+    let folded = match &mut expr.kind {
+        ExprKind::Literal(_) | ExprKind::Variable(_) => None,
+        ExprKind::Grouping(inner) => {
+            fold_expr(inner, interner);
+            inner.as_literal().map(ExprKind::Literal)
+        }
+        ExprKind::Unary(op, operand) => {
+            fold_expr(operand, interner);
+            match (op, operand.as_literal()) {
+                (UnaryOp::Negate, Some(Literal::Number(number))) => {
+                    Some(ExprKind::Literal(Literal::Number(-number)))
+                }
+                (UnaryOp::Not, Some(literal)) => {
+                    Some(ExprKind::Literal(Literal::Bool(!literal.is_truthy())))
+                }
+                _ => None,
+            }
+        }
+        ExprKind::Binary(left, op, right) => {
+            fold_expr(left, interner);
+            fold_expr(right, interner);
+            match (left.as_literal(), right.as_literal()) {
+                (Some(left), Some(right)) => {
+                    binary(*op, left, right, interner).map(ExprKind::Literal)
+                }
+                _ => None,
+            }
+        }
+        ExprKind::Logical(left, op, right) => {
+            fold_expr(left, interner);
+            fold_expr(right, interner);
+            match left.as_literal() {
+                // The left side is the result when it decides the outcome.
+                Some(literal) if literal.is_truthy() == (*op == LogicalOp::Or) => {
+                    Some(ExprKind::Literal(literal))
+                }
+                Some(_) => {
+                    meta = right.meta;
+                    Some(mem::replace(
+                        &mut right.kind,
+                        ExprKind::Literal(Literal::Nil),
+                    ))
+                }
+                None => None,
+            }
+        }
+        ExprKind::Assign(_, value) => {
+            fold_expr(value, interner);
+            None
+        }
+        ExprKind::Call(callee, arguments) => {
+            fold_expr(callee, interner);
+            for argument in arguments {
+                fold_expr(argument, interner);
+            }
+            None
+        }
+    };
+    if let Some(kind) = folded {
+        *expr = Expr::new(kind, meta);
+    }
+}
+
+/// The value of a binary operator on two literals, or `None` if it would be
+/// a runtime error.
+fn binary(op: BinaryOp, left: Literal, right: Literal, interner: &Interner) -> Option<Literal> {
+    match (op, left, right) {
+        (BinaryOp::Equal, _, _) => return Some(Literal::Bool(equal(left, right))),
+        (BinaryOp::NotEqual, _, _) => return Some(Literal::Bool(!equal(left, right))),
+        (BinaryOp::Add, Literal::String(a), Literal::String(b)) => {
+            let text = format!("{}{}", interner.resolve(a)?, interner.resolve(b)?);
+            return Some(Literal::String(interner.intern(&text)));
+        }
+        _ => {}
+    }
+    let (Literal::Number(a), Literal::Number(b)) = (left, right) else {
+        return None;
+    };
+    Some(match op {
+        BinaryOp::Add => Literal::Number(a + b),
+        BinaryOp::Subtract => Literal::Number(a - b),
+        BinaryOp::Multiply => Literal::Number(a * b),
+        BinaryOp::Divide => Literal::Number(a / b),
+        BinaryOp::Less => Literal::Bool(a < b),
+        BinaryOp::LessEqual => Literal::Bool(a <= b),
+        BinaryOp::Greater => Literal::Bool(a > b),
+        BinaryOp::GreaterEqual => Literal::Bool(a >= b),
+        BinaryOp::Equal | BinaryOp::NotEqual => return None,
+    })
+}
+
+fn equal(left: Literal, right: Literal) -> bool {
+    match (left, right) {
+        (Literal::Nil, Literal::Nil) => true,
+        (Literal::Bool(a), Literal::Bool(b)) => a == b,
+        (Literal::Number(a), Literal::Number(b)) => a == b,
+        (Literal::String(a), Literal::String(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{interner::Interner, tokens::TokenMeta};
+
+    use super::{
+        super::{BinaryOp, Expr, ExprKind, Literal, LogicalOp, Stmt, StmtKind, UnaryOp},
+        optimize,
+    };
+
+    fn at(line: usize) -> TokenMeta {
+        TokenMeta::new(line, 0)
+    }
+
+    fn literal(literal: Literal, line: usize) -> Expr {
+        Expr::new(ExprKind::Literal(literal), at(line))
+    }
+
+    fn number(n: f64) -> Expr {
+        literal(Literal::Number(n), 1)
+    }
+
+    fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+        let meta = left.meta;
+        Expr::new(ExprKind::Binary(Box::new(left), op, Box::new(right)), meta)
+    }
+
+    fn print(expr: Expr) -> Stmt {
+        let meta = expr.meta;
+        Stmt::new(StmtKind::Print(expr), meta)
+    }
+
+    /// Optimizes `print expr;` and returns what's left of `expr`.
+    fn fold(expr: Expr, interner: &Interner) -> Expr {
+        let mut statements = vec![print(expr)];
+        optimize(&mut statements, interner);
+        match statements.pop().map(|statement| statement.kind) {
+            Some(StmtKind::Print(expr)) => expr,
+            other => panic!("expected a print statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn folds_arithmetic_keeping_positions() {
+        let interner = Interner::new();
+        let day = binary(
+            binary(
+                literal(Literal::Number(60.0), 3),
+                BinaryOp::Multiply,
+                number(60.0),
+            ),
+            BinaryOp::Multiply,
+            number(24.0),
+        );
+        assert_eq!(fold(day, &interner), literal(Literal::Number(86400.0), 3));
+
+        let grouped = Expr::new(
+            ExprKind::Unary(
+                UnaryOp::Negate,
+                Box::new(Expr::new(
+                    ExprKind::Grouping(Box::new(binary(
+                        number(1.0),
+                        BinaryOp::Divide,
+                        number(0.0),
+                    ))),
+                    at(1),
+                )),
+            ),
+            at(2),
+        );
+        assert_eq!(
+            fold(grouped, &interner),
+            literal(Literal::Number(f64::NEG_INFINITY), 2)
+        );
+    }
+
+    #[test]
+    fn folds_comparisons_strings_and_not() {
+        let interner = Interner::new();
+        let string = |text: &str| literal(Literal::String(interner.intern(text)), 1);
+        let cases = [
+            (
+                binary(number(1.0), BinaryOp::Less, number(2.0)),
+                Literal::Bool(true),
+            ),
+            (
+                binary(number(2.0), BinaryOp::LessEqual, number(1.0)),
+                Literal::Bool(false),
+            ),
+            (
+                binary(
+                    literal(Literal::Nil, 1),
+                    BinaryOp::Equal,
+                    literal(Literal::Bool(false), 1),
+                ),
+                Literal::Bool(false),
+            ),
+            (
+                binary(string("a"), BinaryOp::NotEqual, string("a")),
+                Literal::Bool(false),
+            ),
+            (
+                binary(number(f64::NAN), BinaryOp::Equal, number(f64::NAN)),
+                Literal::Bool(false),
+            ),
+            (
+                binary(string("day"), BinaryOp::Add, string("light")),
+                Literal::String(interner.intern("daylight")),
+            ),
+            (
+                Expr::new(
+                    ExprKind::Unary(UnaryOp::Not, Box::new(literal(Literal::Bool(true), 1))),
+                    at(1),
+                ),
+                Literal::Bool(false),
+            ),
+            (
+                Expr::new(ExprKind::Unary(UnaryOp::Not, Box::new(number(0.0))), at(1)),
+                Literal::Bool(false),
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(fold(expr, &interner), literal(expected, 1));
+        }
+    }
+
+    #[test]
+    fn folds_logical_operators_with_literal_left_sides() {
+        let interner = Interner::new();
+        let x = Expr::new(ExprKind::Variable(interner.intern("x")), at(5));
+        let logical = |left: Literal, op| {
+            Expr::new(
+                ExprKind::Logical(Box::new(literal(left, 1)), op, Box::new(x.clone())),
+                at(1),
+            )
+        };
+        assert_eq!(fold(logical(Literal::Nil, LogicalOp::Or), &interner), x);
+        assert_eq!(
+            fold(logical(Literal::Nil, LogicalOp::And), &interner),
+            literal(Literal::Nil, 1)
+        );
+        assert_eq!(
+            fold(logical(Literal::Number(0.0), LogicalOp::Or), &interner),
+            literal(Literal::Number(0.0), 1)
+        );
+        assert_eq!(
+            fold(logical(Literal::Bool(true), LogicalOp::And), &interner),
+            x
+        );
+    }
+
+    #[test]
+    fn leaves_runtime_errors_and_variables_alone() {
+        let interner = Interner::new();
+        let string = literal(Literal::String(interner.intern("a")), 1);
+        let x = Expr::new(ExprKind::Variable(interner.intern("x")), at(1));
+        let unfoldable = [
+            Expr::new(
+                ExprKind::Unary(UnaryOp::Negate, Box::new(string.clone())),
+                at(1),
+            ),
+            binary(number(1.0), BinaryOp::Add, string.clone()),
+            binary(string, BinaryOp::Less, number(1.0)),
+            binary(x.clone(), BinaryOp::Add, number(1.0)),
+        ];
+        for expr in unfoldable {
+            assert_eq!(fold(expr.clone(), &interner), expr);
+        }
+
+        let partly = binary(
+            x.clone(),
+            BinaryOp::Add,
+            binary(number(1.0), BinaryOp::Add, number(2.0)),
+        );
+        assert_eq!(
+            fold(partly, &interner),
+            binary(x, BinaryOp::Add, number(3.0))
+        );
+    }
+
+    #[test]
+    fn removes_dead_branches() {
+        let interner = Interner::new();
+        let x = || Expr::new(ExprKind::Variable(interner.intern("x")), at(1));
+        let block = |statements: Vec<Stmt>| Stmt::new(StmtKind::Block(statements), at(1));
+        let if_stmt = |condition: Expr, then_branch: Stmt, else_branch: Option<Stmt>| {
+            Stmt::new(
+                StmtKind::If(condition, Box::new(then_branch), else_branch.map(Box::new)),
+                at(1),
+            )
+        };
+        let while_stmt = |condition: Expr, body: Stmt| {
+            Stmt::new(StmtKind::While(condition, Box::new(body)), at(1))
+        };
+
+        let mut statements = vec![
+            if_stmt(
+                literal(Literal::Bool(false), 1),
+                print(number(1.0)),
+                Some(print(number(2.0))),
+            ),
+            if_stmt(
+                binary(number(1.0), BinaryOp::Greater, number(2.0)),
+                print(number(3.0)),
+                None,
+            ),
+            if_stmt(literal(Literal::Nil, 1), block(vec![print(x())]), None),
+            if_stmt(
+                literal(Literal::Number(0.0), 1),
+                block(vec![print(number(4.0))]),
+                None,
+            ),
+            while_stmt(literal(Literal::Bool(false), 1), print(x())),
+            while_stmt(x(), block(vec![])),
+            if_stmt(x(), block(vec![]), Some(block(vec![]))),
+            Stmt::new(StmtKind::Expression(number(5.0)), at(1)),
+        ];
+        optimize(&mut statements, &interner);
+        assert_eq!(
+            statements,
+            vec![
+                print(number(2.0)),
+                block(vec![print(number(4.0))]),
+                while_stmt(x(), block(vec![])),
+                if_stmt(x(), block(vec![]), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_code_after_return() {
+        let interner = Interner::new();
+        let returns = |value: Option<Expr>| Stmt::new(StmtKind::Return(value), at(2));
+        let function = |body: Vec<Stmt>| {
+            Stmt::new(
+                StmtKind::Function(interner.intern("f"), vec![], body),
+                at(1),
+            )
+        };
+        let mut statements = vec![function(vec![
+            print(number(1.0)),
+            Stmt::new(
+                StmtKind::If(
+                    literal(Literal::Bool(true), 2),
+                    Box::new(returns(Some(binary(
+                        number(1.0),
+                        BinaryOp::Add,
+                        number(1.0),
+                    )))),
+                    None,
+                ),
+                at(2),
+            ),
+            print(number(3.0)),
+            returns(None),
+        ])];
+        optimize(&mut statements, &interner);
+        assert_eq!(
+            statements,
+            vec![function(vec![
+                print(number(1.0)),
+                returns(Some(number(2.0)))
+            ])]
+        );
+    }
+}
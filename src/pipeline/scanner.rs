@@ -1,8 +1,14 @@
+use std::mem;
+
+use num::{BigUint, ToPrimitive};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
 use self::state::Position;
 
 use super::{
+    interner::Interner,
     source,
-    tokens::{Token, TokenInfo, TokenMeta},
+    tokens::{Lexeme, LosslessToken, Token, TokenInfo, TokenMeta, Trivia, TriviaKind},
 };
 
 mod state;
@@ -18,97 +24,156 @@ impl ScanError {
         Self { msg }
     }
 
-
     pub fn message(&self) -> &str {
         self.msg.as_ref()
     }
 }
 
-pub fn scan(source: source::Source) -> (Vec<ScanError>, Vec<Token>) {
+/// Result of scanning a single lexeme: either a token, or trivia that
+/// carries no meaning for the parser but is part of the source text.
+enum Scanned {
+    Token(Token),
+    Trivia(Trivia),
+}
+
+/// Scans the source into tokens, interning identifier names and string
+/// literals into the given interner.
+pub fn scan(source: source::Source, interner: &Interner) -> (Vec<ScanError>, Vec<Token>) {
     let mut tokens = vec![];
+    let (errors, eof) = scan_all(&source, interner, |scanned| {
+        if let Scanned::Token(token) = scanned {
+            tokens.push(token);
+        }
+    });
+    tokens.push(eof);
+    (errors, tokens)
+}
+
+/// Scans the source without discarding anything: every token carries the
+/// whitespace, newlines, comments and unscannable text around it, so that
+/// concatenating all tokens with their trivia reproduces the source text.
+///
+/// Trivia on the same line after a token (up to, but excluding, the newline)
+/// trails that token; everything else leads the next token, and whatever is
+/// left at the end of the source leads the EOF token.
+pub fn scan_lossless(
+    source: source::Source,
+    interner: &Interner,
+) -> (Vec<ScanError>, Vec<LosslessToken>) {
+    let mut tokens: Vec<LosslessToken> = vec![];
+    let mut leading = vec![];
+    let mut after_token_on_line = false;
+    let (errors, eof) = scan_all(&source, interner, |scanned| match scanned {
+        Scanned::Token(token) => {
+            tokens.push(LosslessToken::new(mem::take(&mut leading), token, vec![]));
+            after_token_on_line = true;
+        }
+        Scanned::Trivia(trivia) => {
+            if *trivia.kind() == TriviaKind::Newline {
+                after_token_on_line = false;
+            }
+            match tokens.last_mut() {
+                Some(token) if after_token_on_line => token.push_trailing(trivia),
+                _ => leading.push(trivia),
+            }
+        }
+    });
+    tokens.push(LosslessToken::new(leading, eof, vec![]));
+    (errors, tokens)
+}
+
+fn scan_all<F>(source: &source::Source, interner: &Interner, mut emit: F) -> (Vec<ScanError>, Token)
+where
+    F: FnMut(Scanned),
+{
     let mut errors: Vec<ScanError> = vec![];
-    let mut state = ScanState::new(source.text());
-    while !state.is_at_end() {
-        state.reset_segment();
-        match scan_next(&mut state) {
+    let mut state = ScanState::new(source.text(), interner.clone());
+    state.reset_segment();
+    while let Some(c) = state.pop_char() {
+        match scan_next(&mut state, c) {
             Err(err) => {
                 errors.push(err);
+                emit(make_trivia(&mut state, TriviaKind::Skipped));
             }
-            Ok(Some(token)) => {
-                tokens.push(token);
-            }
-            Ok(None) => {}
+            Ok(scanned) => emit(scanned),
         }
+        state.reset_segment();
     }
-    tokens.push(Token::new(String::new(), TokenInfo::EOF, {
+    let eof = Token::from_lexeme(Lexeme::Fixed(""), TokenInfo::EOF, {
         let Position { line, column } = state.current_position();
         TokenMeta::new(*line, *column)
-    }));
-    (errors, tokens)
+    });
+    (errors, eof)
 }
 
-fn scan_next(state: &mut ScanState) -> Result<Option<Token>, ScanError> {
-    if let Some(c) = state.pop_char() {
-        match c {
-            '(' => Ok(Some(make_token(state, |_| TokenInfo::LeftParen))),
-            ')' => Ok(Some(make_token(state, |_| TokenInfo::RightParen))),
-            '{' => Ok(Some(make_token(state, |_| TokenInfo::LeftBrace))),
-            '}' => Ok(Some(make_token(state, |_| TokenInfo::RightBrace))),
-            ':' => Ok(Some(make_token(state, |_| TokenInfo::Colon))),
-            ',' => Ok(Some(make_token(state, |_| TokenInfo::Comma))),
-            '.' => Ok(Some(make_token(state, |_| TokenInfo::Dot))),
-            '-' => Ok(Some(make_token(state, |_| TokenInfo::Minus))),
-            '+' => Ok(Some(make_token(state, |_| TokenInfo::Plus))),
-            '?' => Ok(Some(make_token(state, |_| TokenInfo::QuestionMark))),
-            ';' => Ok(Some(make_token(state, |_| TokenInfo::Semicolon))),
-            '*' => Ok(Some(make_token(state, |_| TokenInfo::Star))),
-            '!' => Ok(Some({
-                if state.match_char('=') {
-                    make_token(state, |_| TokenInfo::BangEqual)
-                } else {
-                    make_token(state, |_| TokenInfo::Bang)
-                }
-            })),
-            '=' => Ok(Some({
-                if state.match_char('=') {
-                    make_token(state, |_| TokenInfo::EqualEqual)
-                } else {
-                    make_token(state, |_| TokenInfo::Equal)
-                }
-            })),
-            '<' => Ok(Some({
-                if state.match_char('=') {
-                    make_token(state, |_| TokenInfo::LessEqual)
-                } else {
-                    make_token(state, |_| TokenInfo::Less)
-                }
-            })),
-            '>' => Ok(Some({
-                if state.match_char('=') {
-                    make_token(state, |_| TokenInfo::GreaterEqual)
-                } else {
-                    make_token(state, |_| TokenInfo::Greater)
-                }
-            })),
-            '/' => {
-                if state.match_char('/') {
-                    skip_line_comment(state);
-                    Ok(None)
-                } else {
-                    Ok(Some(make_token(state, |_| TokenInfo::Slash)))
-                }
+fn scan_next(state: &mut ScanState, c: char) -> Result<Scanned, ScanError> {
+    match c {
+        '(' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::LeftParen))),
+        ')' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::RightParen))),
+        '{' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::LeftBrace))),
+        '}' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::RightBrace))),
+        ':' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Colon))),
+        ',' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Comma))),
+        '.' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Dot))),
+        '-' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Minus))),
+        '+' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Plus))),
+        '?' => Ok(Scanned::Token(make_token(state, |_| {
+            TokenInfo::QuestionMark
+        }))),
+        ';' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Semicolon))),
+        '*' => Ok(Scanned::Token(make_token(state, |_| TokenInfo::Star))),
+        '!' => Ok(Scanned::Token({
+            if state.match_char('=') {
+                make_token(state, |_| TokenInfo::BangEqual)
+            } else {
+                make_token(state, |_| TokenInfo::Bang)
             }
-            '"' => scan_string_literal(state).map(Some),
-            other if other.is_whitespace() => Ok(None),
-            other if other.is_ascii_digit() => scan_number_literal(state).map(Some),
-            other if is_identifier_first(other) => scan_identifier(state).map(Some),
-            other => Err(ScanError::make(
-                state,
-                format!("Unexpected character {}", other),
-            )),
+        })),
+        '=' => Ok(Scanned::Token({
+            if state.match_char('=') {
+                make_token(state, |_| TokenInfo::EqualEqual)
+            } else {
+                make_token(state, |_| TokenInfo::Equal)
+            }
+        })),
+        '<' => Ok(Scanned::Token({
+            if state.match_char('=') {
+                make_token(state, |_| TokenInfo::LessEqual)
+            } else {
+                make_token(state, |_| TokenInfo::Less)
+            }
+        })),
+        '>' => Ok(Scanned::Token({
+            if state.match_char('=') {
+                make_token(state, |_| TokenInfo::GreaterEqual)
+            } else {
+                make_token(state, |_| TokenInfo::Greater)
+            }
+        })),
+        '/' => {
+            if state.match_char('/') {
+                skip_line_comment(state);
+                Ok(make_trivia(state, TriviaKind::LineComment))
+            } else {
+                Ok(Scanned::Token(make_token(state, |_| TokenInfo::Slash)))
+            }
+        }
+        '"' => scan_string_literal(state).map(Scanned::Token),
+        '\n' => Ok(make_trivia(state, TriviaKind::Newline)),
+        '\r' if state.match_char('\n') => Ok(make_trivia(state, TriviaKind::Newline)),
+        other if other.is_whitespace() => {
+            while state
+                .match_pred(|c| c.is_whitespace() && c != '\n' && c != '\r')
+                .is_some()
+            {}
+            Ok(make_trivia(state, TriviaKind::Whitespace))
         }
-    } else {
-        Ok(None)
+        other if other.is_ascii_digit() => scan_number_literal(state, other).map(Scanned::Token),
+        other if is_identifier_first(other) => scan_identifier(state).map(Scanned::Token),
+        other => Err(ScanError::make(
+            state,
+            format!("Unexpected character {}", other),
+        )),
     }
 }
 
@@ -116,30 +181,153 @@ fn make_token<INFO>(state: &mut ScanState, info: INFO) -> Token
 where
     INFO: FnOnce(&str) -> TokenInfo,
 {
-    let (lexeme, start_pos, _) = state.take_segment();
-    let info = info(&lexeme[..]);
-    Token::new(
+    let (lexeme, _, _) = state.segment();
+    let info = info(lexeme);
+    let lexeme = match info.fixed_text() {
+        Some(text) => Lexeme::Fixed(text),
+        None => Lexeme::Owned(lexeme.to_string()),
+    };
+    make_shared_token(state, info, lexeme)
+}
+
+/// Makes a token whose text is already known, without copying the segment.
+fn make_shared_token(state: &mut ScanState, info: TokenInfo, lexeme: Lexeme) -> Token {
+    let (_, start_pos, _) = state.take_segment();
+    Token::from_lexeme(
         lexeme,
         info,
         TokenMeta::new(start_pos.line, start_pos.column),
     )
 }
 
-fn scan_number_literal(state: &mut ScanState) -> Result<Token, ScanError> {
-    fn advance_while_digits(state: &mut ScanState) {
-        while state.match_pred(|c| c.is_ascii_digit()).is_some() {}
+fn make_trivia(state: &mut ScanState, kind: TriviaKind) -> Scanned {
+    let (text, _, _) = state.take_segment();
+    Scanned::Trivia(Trivia::new(kind, text.to_string()))
+}
+
+fn scan_number_literal(state: &mut ScanState, first: char) -> Result<Token, ScanError> {
+    if first == '0' {
+        if state.match_pred(|c| c == 'x' || c == 'X').is_some() {
+            return scan_radix_literal(state, 16, "hexadecimal");
+        }
+        if state.match_pred(|c| c == 'b' || c == 'B').is_some() {
+            return scan_radix_literal(state, 2, "binary");
+        }
     }
-    advance_while_digits(state);
+    let mut problem = scan_digits(state, 10, true).err();
     match state.peek_chars::<2>() {
         Some([dot, digit]) if dot == '.' && digit.is_ascii_digit() => {
             state.pop_char();
-            advance_while_digits(state);
+            problem = problem.or(scan_digits(state, 10, false).err());
         }
         _ => {}
     }
-    Ok(make_token(state, |lexeme| {
-        TokenInfo::NumberLiteral(lexeme.parse().unwrap())
-    }))
+    if state.match_pred(|c| c == 'e' || c == 'E').is_some() {
+        state.match_pred(|c| c == '+' || c == '-');
+        problem = problem.or(match scan_digits(state, 10, false) {
+            Ok(0) => Some("missing exponent digits"),
+            Ok(_) => None,
+            Err(problem) => Some(problem),
+        });
+    }
+    check_number_literal_end(state, problem)?;
+    let (lexeme, _, _) = state.segment();
+    let value: f64 = lexeme
+        .replace('_', "")
+        .parse()
+        .map_err(|_| ScanError::make(state, format!("Malformed number literal {}", lexeme)))?;
+    if value.is_infinite() {
+        Err(ScanError::make(
+            state,
+            format!(
+                "Malformed number literal {}: decimal value too large",
+                lexeme
+            ),
+        ))?;
+    }
+    Ok(make_token(state, |_| TokenInfo::NumberLiteral(value)))
+}
+
+fn scan_radix_literal(
+    state: &mut ScanState,
+    radix: u32,
+    radix_name: &str,
+) -> Result<Token, ScanError> {
+    let problem = match scan_digits(state, radix, false) {
+        Ok(0) => Some("missing digits"),
+        Ok(_) => None,
+        Err(problem) => Some(problem),
+    };
+    check_number_literal_end(state, problem)?;
+    let (lexeme, _, _) = state.segment();
+    // Folded exactly and rounded once, so literals past 2^53 round to the
+    // nearest double like decimal ones do.
+    let digits: Vec<u8> = lexeme[2..].bytes().filter(|c| *c != b'_').collect();
+    let value = BigUint::parse_bytes(&digits, radix)
+        .and_then(|value| value.to_f64())
+        .unwrap_or(f64::INFINITY);
+    if value.is_infinite() {
+        Err(ScanError::make(
+            state,
+            format!(
+                "Malformed number literal {}: {} value too large",
+                lexeme, radix_name
+            ),
+        ))?;
+    }
+    Ok(make_token(state, |_| TokenInfo::NumberLiteral(value)))
+}
+
+/// Consumes a run of digits in the given radix, with `_` allowed only as a
+/// separator between two digits. Returns how many digits were consumed.
+fn scan_digits(
+    state: &mut ScanState,
+    radix: u32,
+    mut after_digit: bool,
+) -> Result<usize, &'static str> {
+    let mut digits = 0;
+    let mut problem = None;
+    let mut after_separator = false;
+    while let Some(c) = state.match_pred(|c| c.is_digit(radix) || c == '_') {
+        if c == '_' {
+            if !after_digit {
+                problem = problem.or(Some("misplaced digit separator"));
+            }
+            after_digit = false;
+            after_separator = true;
+        } else {
+            digits += 1;
+            after_digit = true;
+            after_separator = false;
+        }
+    }
+    if after_separator {
+        problem = problem.or(Some("misplaced digit separator"));
+    }
+    match problem {
+        Some(problem) => Err(problem),
+        None => Ok(digits),
+    }
+}
+
+/// A number literal must not run straight into an identifier or another
+/// digit. Swallows any such trailing characters so that a single malformed
+/// literal produces a single error.
+fn check_number_literal_end(state: &mut ScanState, problem: Option<&str>) -> Result<(), ScanError> {
+    let mut invalid = None;
+    while let Some(c) = state.match_pred(is_identifier_part) {
+        invalid = invalid.or(Some(c));
+    }
+    let problem = match (problem, invalid) {
+        (Some(problem), _) => problem.to_string(),
+        (None, Some(c)) => format!("unexpected character {}", c),
+        (None, None) => return Ok(()),
+    };
+    let (lexeme, _, _) = state.segment();
+    Err(ScanError::make(
+        state,
+        format!("Malformed number literal {}: {}", lexeme, problem),
+    ))
 }
 
 fn scan_string_literal(state: &mut ScanState) -> Result<Token, ScanError> {
@@ -150,14 +338,19 @@ fn scan_string_literal(state: &mut ScanState) -> Result<Token, ScanError> {
             "Unterminated string literal".to_string(),
         ))?;
     }
-    Ok(make_token(state, |lexeme| {
-        TokenInfo::StringLiteral(lexeme.trim_matches('"').to_string())
-    }))
+    let (lexeme, _, _) = state.segment();
+    let (symbol, contents) = state.intern_shared(lexeme.trim_matches('"'));
+    Ok(make_shared_token(
+        state,
+        TokenInfo::StringLiteral(symbol),
+        Lexeme::Quoted(contents),
+    ))
 }
 
 fn scan_identifier(state: &mut ScanState) -> Result<Token, ScanError> {
     while state.match_pred(is_identifier_part).is_some() {}
-    Ok(make_token(state, |lexeme| match lexeme {
+    let (lexeme, _, _) = state.segment();
+    let info = match lexeme {
         "and" => TokenInfo::And,
         "class" => TokenInfo::Class,
         "else" => TokenInfo::Else,
@@ -173,34 +366,216 @@ fn scan_identifier(state: &mut ScanState) -> Result<Token, ScanError> {
         "true" => TokenInfo::True,
         "var" => TokenInfo::Var,
         "while" => TokenInfo::While,
-        other => TokenInfo::Identifier(other.to_string()),
-    }))
+        // Most names are already in NFC, and can be interned as they are
+        // and share the interner's text.
+        other if is_nfc_quick(other.chars()) == IsNormalized::Yes => {
+            let (symbol, text) = state.intern_shared(other);
+            return Ok(make_shared_token(
+                state,
+                TokenInfo::Identifier(symbol),
+                Lexeme::Interned(text),
+            ));
+        }
+        other => TokenInfo::Identifier(state.intern(&other.nfc().collect::<String>())),
+    };
+    Ok(make_token(state, |_| info))
 }
 
+// Identifiers follow UAX #31 (XID_Start XID_Continue*), with `_` also allowed
+// as the first character. Identifier names are NFC-normalized so that
+// canonically equivalent spellings refer to the same name.
 fn is_identifier_first(c: char) -> bool {
-    c == '_' || c.is_ascii_alphabetic()
+    c == '_' || unicode_ident::is_xid_start(c)
 }
 fn is_identifier_part(c: char) -> bool {
-    c.is_ascii_alphanumeric()
+    unicode_ident::is_xid_continue(c)
 }
 
+/// Comments run to the `\n`, and to the `\r` of a `\r\n`, which is left
+/// for the newline. A lone `\r` doesn't end them.
 fn skip_line_comment(state: &mut ScanState) {
-    while state.match_pred(|c| c != '\n').is_some() {}
-    state.reset_segment();
+    while state.peek_chars() != Some(['\r', '\n']) && state.match_pred(|c| c != '\n').is_some() {}
 }
 
 #[cfg(test)]
 mod test {
+    use std::rc::Rc;
+
     use crate::pipeline::{
         source,
-        tokens::{Token, TokenInfo, TokenMeta},
+        tokens::{Token, TokenInfo, TokenMeta, TriviaKind, KEYWORDS},
     };
 
-    use super::{scan, ScanError};
+    use super::{scan, scan_lossless, Interner, ScanError};
+
+    fn scan_infos(interner: &Interner, text: &str) -> (Vec<String>, Vec<TokenInfo>) {
+        let (errors, tokens) = scan(source::from_repl_input(text), interner);
+        (
+            errors.iter().map(|err| err.message().to_string()).collect(),
+            tokens.iter().map(|token| token.info().clone()).collect(),
+        )
+    }
+
+    #[test]
+    fn number_literals() {
+        let interner = Interner::new();
+        let (errors, infos) = scan_infos(
+            &interner,
+            "0xFF 0Xa_b 0b1010 1e-9 2.5E+3 1_000_000 0 007 12.5_5 \
+             0x20000000000001F 0xFFFFFFFFFFFFFFFF",
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            infos,
+            vec![
+                TokenInfo::NumberLiteral(255.0),
+                TokenInfo::NumberLiteral(171.0),
+                TokenInfo::NumberLiteral(10.0),
+                TokenInfo::NumberLiteral(1e-9),
+                TokenInfo::NumberLiteral(2500.0),
+                TokenInfo::NumberLiteral(1_000_000.0),
+                TokenInfo::NumberLiteral(0.0),
+                TokenInfo::NumberLiteral(7.0),
+                TokenInfo::NumberLiteral(12.55),
+                // 2^57 + 31 rounds up; rounding after each digit gave 2^57.
+                TokenInfo::NumberLiteral(144115188075855904.0),
+                TokenInfo::NumberLiteral(18446744073709551616.0),
+                TokenInfo::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords() {
+        let interner = Interner::new();
+        for (keyword, info) in KEYWORDS {
+            let (errors, infos) = scan_infos(&interner, keyword);
+            assert_eq!(errors, Vec::<String>::new());
+            assert_eq!(infos, vec![info.clone(), TokenInfo::EOF]);
+        }
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let interner = Interner::new();
+        let (errors, infos) = scan_infos(
+            &interner,
+            "my_var _x1 переменная_2 变量 καλη\u{301}μέρα ok٣",
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            infos,
+            vec![
+                TokenInfo::Identifier(interner.intern("my_var")),
+                TokenInfo::Identifier(interner.intern("_x1")),
+                TokenInfo::Identifier(interner.intern("переменная_2")),
+                TokenInfo::Identifier(interner.intern("变量")),
+                TokenInfo::Identifier(interner.intern("καλήμέρα")),
+                TokenInfo::Identifier(interner.intern("ok٣")),
+                TokenInfo::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers_are_nfc_normalized() {
+        let interner = Interner::new();
+        let (_, composed) = scan_infos(&interner, "caf\u{e9}");
+        let (_, decomposed) = scan_infos(&interner, "cafe\u{301}");
+        assert_eq!(composed, decomposed);
+    }
+
+    #[test]
+    fn names_share_the_interners_text() {
+        let interner = Interner::new();
+        let (_, tokens) = scan(
+            source::from_repl_input("name \"name\" name cafe\u{301}"),
+            &interner,
+        );
+        let shared =
+            |text: &str| Rc::strong_count(&interner.resolve(interner.intern(text)).unwrap());
+        let (name, cafe) = (shared("name"), shared("caf\u{e9}"));
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.to_string())
+                .collect::<Vec<_>>(),
+            ["name", "\"name\"", "name", "cafe\u{301}", ""]
+        );
+        assert_eq!(tokens[1].lexeme_parts(), ["\"", "name", "\""]);
+        assert_eq!(tokens[0].lexeme_parts(), ["name", "", ""]);
+        drop(tokens);
+        // Both identifiers and the string literal shared the text; the
+        // identifier that had to be normalized kept its own.
+        assert_eq!(name - shared("name"), 3);
+        assert_eq!(cafe - shared("caf\u{e9}"), 0);
+    }
+
+    #[test]
+    fn non_identifier_characters() {
+        let interner = Interner::new();
+        let (errors, infos) = scan_infos(&interner, "a\u{2062}b 1x2 €");
+        assert_eq!(
+            errors,
+            vec![
+                "Unexpected character \u{2062}",
+                "Malformed number literal 1x2: unexpected character x",
+                "Unexpected character €",
+            ]
+        );
+        assert_eq!(
+            infos,
+            vec![
+                TokenInfo::Identifier(interner.intern("a")),
+                TokenInfo::Identifier(interner.intern("b")),
+                TokenInfo::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_number_literals() {
+        let interner = Interner::new();
+        let hex_overflow = format!("0x{}", "f".repeat(257));
+        let (errors, infos) = scan_infos(
+            &interner,
+            &format!(
+                "0x 1e 1__0 1_ 0b102 0x_1 12abc 1e+ 1e400 {} 3;",
+                hex_overflow
+            ),
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Malformed number literal 0x: missing digits",
+                "Malformed number literal 1e: missing exponent digits",
+                "Malformed number literal 1__0: misplaced digit separator",
+                "Malformed number literal 1_: misplaced digit separator",
+                "Malformed number literal 0b102: unexpected character 2",
+                "Malformed number literal 0x_1: misplaced digit separator",
+                "Malformed number literal 12abc: unexpected character a",
+                "Malformed number literal 1e+: missing exponent digits",
+                "Malformed number literal 1e400: decimal value too large",
+                &format!(
+                    "Malformed number literal {}: hexadecimal value too large",
+                    hex_overflow
+                ),
+            ]
+        );
+        assert_eq!(
+            infos,
+            vec![
+                TokenInfo::NumberLiteral(3.0),
+                TokenInfo::Semicolon,
+                TokenInfo::EOF,
+            ]
+        );
+    }
 
     #[test]
     fn weird_case() {
-        let (errors, tokens) = scan(source::from_repl_input("//first comment\n{123.456.789\nand.123.treco&?:// zuera\n \"lol\" )!=!<=<>=>/bla \"erro"));
+        let interner = Interner::new();
+        let (errors, tokens) = scan(source::from_repl_input("//first comment\n{123.456.789\nand.123.treco&?:// zuera\n \"lol\" )!=!<=<>=>/bla \"erro"), &interner);
         assert_eq!(
             errors,
             vec![
@@ -237,7 +612,7 @@ mod test {
                 Token::new(".".to_string(), TokenInfo::Dot, TokenMeta::new(2, 8)),
                 Token::new(
                     "treco".to_string(),
-                    TokenInfo::Identifier("treco".to_string()),
+                    TokenInfo::Identifier(interner.intern("treco")),
                     TokenMeta::new(2, 13)
                 ),
                 Token::new(
@@ -248,7 +623,7 @@ mod test {
                 Token::new(":".to_string(), TokenInfo::Colon, TokenMeta::new(2, 16)),
                 Token::new(
                     "\"lol\"".to_string(),
-                    TokenInfo::StringLiteral("lol".to_string()),
+                    TokenInfo::StringLiteral(interner.intern("lol")),
                     TokenMeta::new(3, 6)
                 ),
                 Token::new(")".to_string(), TokenInfo::RightParen, TokenMeta::new(3, 8)),
@@ -273,11 +648,108 @@ mod test {
                 Token::new("/".to_string(), TokenInfo::Slash, TokenMeta::new(3, 18)),
                 Token::new(
                     "bla".to_string(),
-                    TokenInfo::Identifier("bla".to_string()),
+                    TokenInfo::Identifier(interner.intern("bla")),
                     TokenMeta::new(3, 21)
                 ),
                 Token::new("".to_string(), TokenInfo::EOF, TokenMeta::new(3, 27)),
             ]
         );
     }
+
+    #[test]
+    fn lossless_round_trip() {
+        for text in [
+            "",
+            "   \n// only a comment",
+            "//first comment\n{123.456.789\nand.123.treco&?:// zuera\n \"lol\" )!=!<=<>=>/bla \"erro",
+            "var x = 0x_1 + 1e;\r\n\tprint x; // done\r\n\r\n",
+            "fun f(a, b) {\n  return a\u{3000}+ b; €\n}\n",
+        ] {
+            let (_, tokens) = scan_lossless(source::from_repl_input(text), &Interner::new());
+            let round_trip: String = tokens.iter().map(|token| token.to_string()).collect();
+            assert_eq!(round_trip, text);
+        }
+    }
+
+    #[test]
+    fn line_comments_end_at_newlines() {
+        let interner = Interner::new();
+        let (_, infos) = scan_infos(&interner, "// a\rb");
+        assert_eq!(infos, vec![TokenInfo::EOF]);
+
+        let (_, tokens) = scan_lossless(source::from_repl_input("// a\r\nb"), &interner);
+        let leading: Vec<_> = tokens[0]
+            .leading()
+            .iter()
+            .map(|trivia| (*trivia.kind(), trivia.text()))
+            .collect();
+        assert_eq!(
+            leading,
+            [
+                (TriviaKind::LineComment, "// a"),
+                (TriviaKind::Newline, "\r\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn lossless_trivia_attachment() {
+        let (errors, tokens) = scan_lossless(
+            source::from_repl_input("// header\nvar  x; // note\n\n  @x\n"),
+            &Interner::new(),
+        );
+        assert_eq!(
+            errors,
+            vec![ScanError {
+                msg: "Unexpected character @".to_string()
+            }]
+        );
+        let lexemes: Vec<_> = tokens.iter().map(|token| token.token().lexeme()).collect();
+        let summary: Vec<(Vec<TriviaKind>, &str, Vec<TriviaKind>)> = tokens
+            .iter()
+            .zip(&lexemes)
+            .map(|(token, lexeme)| {
+                (
+                    token
+                        .leading()
+                        .iter()
+                        .map(|trivia| *trivia.kind())
+                        .collect(),
+                    lexeme.as_ref(),
+                    token
+                        .trailing()
+                        .iter()
+                        .map(|trivia| *trivia.kind())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    vec![TriviaKind::LineComment, TriviaKind::Newline],
+                    "var",
+                    vec![TriviaKind::Whitespace]
+                ),
+                (vec![], "x", vec![]),
+                (
+                    vec![],
+                    ";",
+                    vec![TriviaKind::Whitespace, TriviaKind::LineComment]
+                ),
+                (
+                    vec![
+                        TriviaKind::Newline,
+                        TriviaKind::Newline,
+                        TriviaKind::Whitespace,
+                        TriviaKind::Skipped
+                    ],
+                    "x",
+                    vec![]
+                ),
+                (vec![TriviaKind::Newline], "", vec![]),
+            ]
+        );
+    }
 }
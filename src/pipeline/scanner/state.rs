@@ -1,19 +1,25 @@
-use std::mem;
+use std::rc::Rc;
+
+use crate::pipeline::interner::{Interner, Symbol};
 
 pub struct ScanState<'s> {
     source: &'s str,
-    segment: String,
+    // What was left of the source when the segment started; the segment is
+    // the part of it that has been consumed since.
+    segment_start: &'s str,
     start_pos: Position,
     current_pos: Position,
+    interner: Interner,
 }
 
 impl<'s> ScanState<'s> {
-    pub fn new(source: &'s str) -> Self {
+    pub fn new(source: &'s str, interner: Interner) -> Self {
         Self {
             source,
-            segment: String::new(),
+            segment_start: source,
             start_pos: Position::zero(),
             current_pos: Position::zero(),
+            interner,
         }
     }
 }
@@ -39,22 +45,42 @@ impl<'s> ScanState<'s> {
         self.source.is_empty()
     }
 
+    pub fn intern(&self, string: &str) -> Symbol {
+        self.interner.intern(string)
+    }
+
+    /// Interns `string` and also returns the interner's copy of it, which
+    /// tokens can share instead of owning the text.
+    pub fn intern_shared(&self, string: &str) -> (Symbol, Rc<str>) {
+        let symbol = self.interner.intern(string);
+        let text = self
+            .interner
+            .resolve(symbol)
+            .expect("symbol was just interned");
+        (symbol, text)
+    }
+
     pub fn current_position(&self) -> &Position {
         &self.current_pos
     }
 
-    pub fn segment(&self) -> (&str, &Position, &Position) {
-        (&self.segment, &self.start_pos, &self.current_pos)
+    pub fn segment(&self) -> (&'s str, &Position, &Position) {
+        let consumed = self.segment_start.len() - self.source.len();
+        (
+            &self.segment_start[..consumed],
+            &self.start_pos,
+            &self.current_pos,
+        )
     }
 
-    pub fn take_segment(&mut self) -> (String, Position, Position) {
-        let segment = mem::take(&mut self.segment);
-        self.start_pos = self.current_pos;
+    pub fn take_segment(&mut self) -> (&'s str, Position, Position) {
+        let (segment, _, _) = self.segment();
+        self.reset_segment();
         (segment, self.start_pos, self.current_pos)
     }
 
     pub fn reset_segment(&mut self) {
-        self.segment.clear();
+        self.segment_start = self.source;
         self.start_pos = self.current_pos;
     }
 
@@ -69,14 +95,12 @@ impl<'s> ScanState<'s> {
                 self.current_pos.line += newlines.count() + 1;
             }
         }
-        self.segment.push_str(consumed);
         consumed
     }
 
     pub fn pop_char(&mut self) -> Option<char> {
-        self.source.chars().next().map(|c| {
+        self.source.chars().next().inspect(|c| {
             self.advance(c.len_utf8());
-            c
         })
     }
 
@@ -84,10 +108,13 @@ impl<'s> ScanState<'s> {
     where
         F: FnOnce(char) -> bool,
     {
-        self.source.chars().next().filter(|c| pred(*c)).map(|c| {
-            self.advance(c.len_utf8());
-            c
-        })
+        self.source
+            .chars()
+            .next()
+            .filter(|c| pred(*c))
+            .inspect(|c| {
+                self.advance(c.len_utf8());
+            })
     }
 
     pub fn match_char(&mut self, expected: char) -> bool {
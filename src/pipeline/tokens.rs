@@ -1,14 +1,35 @@
-use std::fmt::{self, Display, Formatter};
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use super::interner::Symbol;
 
-#[derive(PartialEq)]
 pub struct Token {
-    lexeme: String,
+    lexeme: Lexeme,
     info: TokenInfo,
     meta: TokenMeta,
 }
 
+/// The text of a token. Only number literals and identifiers that aren't
+/// in NFC own a copy of it; punctuation and keywords have fixed text, and
+/// other identifiers and string literals share the interner's copy.
+#[derive(Clone)]
+pub(crate) enum Lexeme {
+    Fixed(&'static str),
+    Interned(Rc<str>),
+    /// A string literal, whose interned contents lack the quotes.
+    Quoted(Rc<str>),
+    Owned(String),
+}
+
 impl Token {
     pub fn new(lexeme: String, info: TokenInfo, meta: TokenMeta) -> Self {
+        Self::from_lexeme(Lexeme::Owned(lexeme), info, meta)
+    }
+
+    pub(crate) fn from_lexeme(lexeme: Lexeme, info: TokenInfo, meta: TokenMeta) -> Self {
         Self { lexeme, info, meta }
     }
 
@@ -16,8 +37,24 @@ impl Token {
         &self.meta
     }
 
-    pub fn lexeme(&self) -> &str {
-        self.lexeme.as_ref()
+    /// The token's text. Only string literals have to build it; use
+    /// `lexeme_parts` to read those without allocating.
+    pub fn lexeme(&self) -> Cow<'_, str> {
+        match self.lexeme_parts() {
+            [text, "", ""] => Cow::Borrowed(text),
+            parts => Cow::Owned(parts.concat()),
+        }
+    }
+
+    /// Pieces that make up the token's text when put together. Only string
+    /// literals, whose contents sit between the quotes, need more than one.
+    pub fn lexeme_parts(&self) -> [&str; 3] {
+        match &self.lexeme {
+            Lexeme::Fixed(text) => [text, "", ""],
+            Lexeme::Interned(text) => [text, "", ""],
+            Lexeme::Quoted(contents) => ["\"", contents, "\""],
+            Lexeme::Owned(text) => [text, "", ""],
+        }
     }
 
     pub fn info(&self) -> &TokenInfo {
@@ -25,9 +62,23 @@ impl Token {
     }
 }
 
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.info == other.info
+            && self.meta == other.meta
+            && self
+                .lexeme_parts()
+                .into_iter()
+                .flat_map(str::bytes)
+                .eq(other.lexeme_parts().into_iter().flat_map(str::bytes))
+    }
+}
+
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.lexeme)
+        self.lexeme_parts()
+            .into_iter()
+            .try_for_each(|part| f.write_str(part))
     }
 }
 
@@ -36,12 +87,89 @@ impl fmt::Debug for Token {
         write!(
             f,
             "Token<{},{},{:?}>({})",
-            self.meta.line, self.meta.column, self.info, self.lexeme
+            self.meta.line, self.meta.column, self.info, self
         )
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
+pub struct LosslessToken {
+    leading: Vec<Trivia>,
+    token: Token,
+    trailing: Vec<Trivia>,
+}
+
+impl LosslessToken {
+    pub fn new(leading: Vec<Trivia>, token: Token, trailing: Vec<Trivia>) -> Self {
+        Self {
+            leading,
+            token,
+            trailing,
+        }
+    }
+
+    pub fn leading(&self) -> &[Trivia] {
+        self.leading.as_ref()
+    }
+
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        self.trailing.as_ref()
+    }
+
+    pub fn push_trailing(&mut self, trivia: Trivia) {
+        self.trailing.push(trivia);
+    }
+}
+
+impl Display for LosslessToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for trivia in &self.leading {
+            f.write_str(trivia.text())?;
+        }
+        write!(f, "{}", self.token)?;
+        for trivia in &self.trailing {
+            f.write_str(trivia.text())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    kind: TriviaKind,
+    text: String,
+}
+
+impl Trivia {
+    pub fn new(kind: TriviaKind, text: String) -> Self {
+        Self { kind, text }
+    }
+
+    pub fn kind(&self) -> &TriviaKind {
+        &self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_ref()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    // A single "\n" or "\r\n"
+    Newline,
+    // From "//" up to, but excluding, the end of the line
+    LineComment,
+    // Text that could not be scanned into a token
+    Skipped,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TokenMeta {
     line: usize,
     column: usize,
@@ -61,7 +189,26 @@ impl TokenMeta {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Reserved words and the tokens they scan to.
+pub const KEYWORDS: &[(&str, TokenInfo)] = &[
+    ("and", TokenInfo::And),
+    ("class", TokenInfo::Class),
+    ("else", TokenInfo::Else),
+    ("false", TokenInfo::False),
+    ("for", TokenInfo::For),
+    ("fun", TokenInfo::Fun),
+    ("if", TokenInfo::If),
+    ("nil", TokenInfo::Nil),
+    ("or", TokenInfo::Or),
+    ("return", TokenInfo::Return),
+    ("super", TokenInfo::Super),
+    ("this", TokenInfo::This),
+    ("true", TokenInfo::True),
+    ("var", TokenInfo::Var),
+    ("while", TokenInfo::While),
+];
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenInfo {
     LeftParen,
     RightParen,
@@ -86,8 +233,8 @@ pub enum TokenInfo {
     Less,
     LessEqual,
     // Literals
-    Identifier(String),
-    StringLiteral(String),
+    Identifier(Symbol),
+    StringLiteral(Symbol),
     NumberLiteral(f64),
     // Keywords
     And,
@@ -108,3 +255,41 @@ pub enum TokenInfo {
     // EOF
     EOF,
 }
+
+impl TokenInfo {
+    /// The text of tokens that are always spelled the same way.
+    pub fn fixed_text(&self) -> Option<&'static str> {
+        let text = match self {
+            TokenInfo::LeftParen => "(",
+            TokenInfo::RightParen => ")",
+            TokenInfo::LeftBrace => "{",
+            TokenInfo::RightBrace => "}",
+            TokenInfo::Colon => ":",
+            TokenInfo::Comma => ",",
+            TokenInfo::Dot => ".",
+            TokenInfo::Minus => "-",
+            TokenInfo::Plus => "+",
+            TokenInfo::QuestionMark => "?",
+            TokenInfo::Semicolon => ";",
+            TokenInfo::Slash => "/",
+            TokenInfo::Star => "*",
+            TokenInfo::Bang => "!",
+            TokenInfo::BangEqual => "!=",
+            TokenInfo::Equal => "=",
+            TokenInfo::EqualEqual => "==",
+            TokenInfo::Greater => ">",
+            TokenInfo::GreaterEqual => ">=",
+            TokenInfo::Less => "<",
+            TokenInfo::LessEqual => "<=",
+            TokenInfo::Identifier(_)
+            | TokenInfo::StringLiteral(_)
+            | TokenInfo::NumberLiteral(_) => return None,
+            TokenInfo::EOF => "",
+            keyword => KEYWORDS
+                .iter()
+                .find(|(_, info)| info == keyword)
+                .map(|(text, _)| *text)?,
+        };
+        Some(text)
+    }
+}
@@ -1,5 +1,130 @@
+//! Runtime values. By default `RTValue` is a tagged enum; the `nan-boxing`
+//! feature packs it into the bits of an `f64` instead. Both representations
+//! have the same API, so code outside this module builds values with the
+//! constructors and inspects them through `unpack`.
+
+use std::fmt::{self, Debug, Formatter};
+
+use super::interner::Symbol;
+
+mod convert;
+pub use convert::{FromLox, FromLoxError, ToLox};
+
+#[cfg(not(feature = "nan-boxing"))]
+mod tagged;
+#[cfg(not(feature = "nan-boxing"))]
+pub use tagged::RTValue;
+
+#[cfg(feature = "nan-boxing")]
+mod nan_boxed;
+#[cfg(feature = "nan-boxing")]
+pub use nan_boxed::RTValue;
+
+/// An `RTValue` taken apart, for matching on.
 #[derive(Debug, Copy, Clone)]
-pub enum RTValue {
+pub enum Unpacked {
     Null,
     Number(f64),
+    String(Symbol),
+}
+
+impl RTValue {
+    pub fn as_number(&self) -> Option<f64> {
+        match self.unpack() {
+            Unpacked::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<Symbol> {
+        match self.unpack() {
+            Unpacked::String(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self.unpack(), Unpacked::Null)
+    }
+
+    /// The name of the value's type, as Lox programs know it.
+    pub fn type_name(&self) -> &'static str {
+        match self.unpack() {
+            Unpacked::Null => "nil",
+            Unpacked::Number(_) => "number",
+            Unpacked::String(_) => "string",
+        }
+    }
+
+    /// `nil` is the only falsey value until the language has booleans.
+    pub fn is_falsey(&self) -> bool {
+        self.is_null()
+    }
+}
+
+impl From<Unpacked> for RTValue {
+    fn from(unpacked: Unpacked) -> Self {
+        match unpacked {
+            Unpacked::Null => RTValue::null(),
+            Unpacked::Number(number) => RTValue::number(number),
+            Unpacked::String(symbol) => RTValue::string(symbol),
+        }
+    }
+}
+
+impl Debug for RTValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.unpack().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::interner::Interner;
+
+    use super::{RTValue, Unpacked};
+
+    #[test]
+    fn constructs_and_unpacks() {
+        let interner = Interner::new();
+        interner.intern("a");
+        let symbol = interner.intern("b");
+
+        assert!(RTValue::null().is_null());
+        assert!(RTValue::null().is_falsey());
+        assert_eq!(RTValue::null().as_number(), None);
+
+        for number in [0.0, -0.0, 1.5, -1e300, f64::INFINITY, f64::MIN_POSITIVE] {
+            let value = RTValue::number(number);
+            assert_eq!(value.as_number().map(f64::to_bits), Some(number.to_bits()));
+            assert!(!value.is_falsey());
+            assert_eq!(value.as_string(), None);
+        }
+        assert!(RTValue::number(f64::NAN).as_number().unwrap().is_nan());
+        assert!(RTValue::number(-f64::NAN).as_number().unwrap().is_nan());
+
+        let string = RTValue::string(symbol);
+        assert_eq!(string.as_string(), Some(symbol));
+        assert_eq!(string.as_number(), None);
+        assert!(!string.is_null());
+        assert!(matches!(
+            RTValue::from(Unpacked::String(symbol)).unpack(),
+            Unpacked::String(s) if s == symbol
+        ));
+    }
+
+    #[test]
+    fn debug_format() {
+        let symbol = Interner::new().intern("a");
+        assert_eq!(format!("{:?}", RTValue::null()), "Null");
+        assert_eq!(format!("{:?}", RTValue::number(-2.5)), "Number(-2.5)");
+        assert_eq!(
+            format!("{:?}", RTValue::string(symbol)),
+            "String(Symbol(0))"
+        );
+        assert_eq!(
+            format!("{:?}", Some(RTValue::number(1.0))),
+            "Some(Number(1.0))"
+        );
+    }
 }
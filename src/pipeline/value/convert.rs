@@ -0,0 +1,238 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::pipeline::interner::Interner;
+
+use super::{RTValue, Unpacked};
+
+/// Rust values that can be handed to Lox code. Strings are interned into
+/// `interner`, which must be the one the receiving chunk uses.
+pub trait ToLox {
+    fn to_lox(self, interner: &Interner) -> RTValue;
+}
+
+/// Rust values that Lox values can be read back as.
+pub trait FromLox: Sized {
+    fn from_lox(value: RTValue, interner: &Interner) -> Result<Self, FromLoxError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromLoxError {
+    expected: &'static str,
+    found: &'static str,
+}
+
+impl FromLoxError {
+    fn new(expected: &'static str, value: RTValue) -> Self {
+        Self {
+            expected,
+            found: value.type_name(),
+        }
+    }
+
+    pub fn expected(&self) -> &'static str {
+        self.expected
+    }
+
+    pub fn found(&self) -> &'static str {
+        self.found
+    }
+}
+
+impl Display for FromLoxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for FromLoxError {}
+
+impl ToLox for RTValue {
+    fn to_lox(self, _: &Interner) -> RTValue {
+        self
+    }
+}
+
+impl FromLox for RTValue {
+    fn from_lox(value: RTValue, _: &Interner) -> Result<Self, FromLoxError> {
+        Ok(value)
+    }
+}
+
+impl ToLox for () {
+    fn to_lox(self, _: &Interner) -> RTValue {
+        RTValue::null()
+    }
+}
+
+impl FromLox for () {
+    fn from_lox(value: RTValue, _: &Interner) -> Result<Self, FromLoxError> {
+        if value.is_null() {
+            Ok(())
+        } else {
+            Err(FromLoxError::new("nil", value))
+        }
+    }
+}
+
+macro_rules! impl_number {
+    ($($num:ty),*) => {$(
+        impl ToLox for $num {
+            fn to_lox(self, _: &Interner) -> RTValue {
+                RTValue::number(f64::from(self))
+            }
+        }
+    )*};
+}
+
+// Only types that convert to `f64` without rounding.
+impl_number!(f64, f32, i8, i16, i32, u8, u16, u32);
+
+impl FromLox for f64 {
+    fn from_lox(value: RTValue, _: &Interner) -> Result<Self, FromLoxError> {
+        value
+            .as_number()
+            .ok_or_else(|| FromLoxError::new("number", value))
+    }
+}
+
+macro_rules! impl_integer {
+    ($end:expr; $($int:ty),*) => {$(
+        /// Accepts numbers with no fractional part that fit the type.
+        impl FromLox for $int {
+            fn from_lox(value: RTValue, _: &Interner) -> Result<Self, FromLoxError> {
+                match value.as_number() {
+                    Some(number)
+                        if number.fract() == 0.0
+                            && number >= <$int>::MIN as f64
+                            && number < $end(<$int>::BITS) =>
+                    {
+                        Ok(number as $int)
+                    }
+                    _ => Err(FromLoxError::new(stringify!($int), value)),
+                }
+            }
+        }
+    )*};
+}
+
+// The bounds compare against the powers of two just past `MAX`, which
+// `f64` holds exactly. `MAX as f64` rounds up to them for 64-bit types.
+fn signed_end(bits: u32) -> f64 {
+    2f64.powi(bits as i32 - 1)
+}
+
+fn unsigned_end(bits: u32) -> f64 {
+    2f64.powi(bits as i32)
+}
+
+impl_integer!(signed_end; i32, i64);
+impl_integer!(unsigned_end; u32, usize);
+
+impl ToLox for &str {
+    fn to_lox(self, interner: &Interner) -> RTValue {
+        RTValue::string(interner.intern(self))
+    }
+}
+
+impl ToLox for String {
+    fn to_lox(self, interner: &Interner) -> RTValue {
+        self.as_str().to_lox(interner)
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: RTValue, interner: &Interner) -> Result<Self, FromLoxError> {
+        match value.unpack() {
+            Unpacked::String(symbol) => interner
+                .resolve(symbol)
+                .map(|string| string.to_string())
+                .ok_or_else(|| FromLoxError::new("string", value)),
+            _ => Err(FromLoxError::new("string", value)),
+        }
+    }
+}
+
+/// `None` is `nil`.
+impl<T: ToLox> ToLox for Option<T> {
+    fn to_lox(self, interner: &Interner) -> RTValue {
+        match self {
+            Some(value) => value.to_lox(interner),
+            None => RTValue::null(),
+        }
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: RTValue, interner: &Interner) -> Result<Self, FromLoxError> {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            T::from_lox(value, interner).map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{interner::Interner, value::RTValue};
+
+    use super::{FromLox, ToLox};
+
+    fn round_trip<T: ToLox + FromLox>(value: T, interner: &Interner) -> T {
+        T::from_lox(value.to_lox(interner), interner).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let interner = Interner::new();
+        assert_eq!(round_trip(2.5, &interner), 2.5);
+        assert_eq!(round_trip(-7i32, &interner), -7);
+        assert_eq!(round_trip(u32::MAX, &interner), u32::MAX);
+        assert_eq!(round_trip("hi".to_string(), &interner), "hi");
+        assert_eq!(round_trip(Some(1.0), &interner), Some(1.0));
+        assert_eq!(round_trip(None::<String>, &interner), None);
+        round_trip((), &interner);
+        assert_eq!(i64::from_lox(3u8.to_lox(&interner), &interner), Ok(3));
+        assert_eq!(usize::from_lox(RTValue::number(42.0), &interner), Ok(42));
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let interner = Interner::new();
+        let err = f64::from_lox("a".to_lox(&interner), &interner).unwrap_err();
+        assert_eq!(err.to_string(), "Expected number, found string");
+        let err = String::from_lox(RTValue::null(), &interner).unwrap_err();
+        assert_eq!(err.to_string(), "Expected string, found nil");
+        for number in [0.5, -1.0, 4294967296.0, f64::NAN] {
+            let err = u32::from_lox(RTValue::number(number), &interner).unwrap_err();
+            assert_eq!((err.expected(), err.found()), ("u32", "number"));
+        }
+        assert!(<()>::from_lox(RTValue::number(0.0), &interner).is_err());
+        // 2^63 and 2^64 are one past the largest i64 and u64.
+        let two_63 = 2f64.powi(63);
+        assert!(i64::from_lox(RTValue::number(two_63), &interner).is_err());
+        assert_eq!(
+            i64::from_lox(RTValue::number(-two_63), &interner),
+            Ok(i64::MIN)
+        );
+        assert!(i64::from_lox(RTValue::number(-two_63 * 2.0), &interner).is_err());
+        if usize::BITS == 64 {
+            let two_64 = 2f64.powi(64);
+            assert!(usize::from_lox(RTValue::number(two_64), &interner).is_err());
+            assert_eq!(
+                usize::from_lox(RTValue::number(two_64 - 2048.0), &interner),
+                Ok(usize::MAX - 2047)
+            );
+        }
+        assert!(i32::from_lox(RTValue::number(2147483648.0), &interner).is_err());
+        assert_eq!(
+            i32::from_lox(RTValue::number(-2147483648.0), &interner),
+            Ok(i32::MIN)
+        );
+        // Symbols don't resolve in tables they weren't interned into.
+        let other = Interner::new();
+        other.intern("x");
+        let symbol = "y".to_lox(&other);
+        assert!(String::from_lox(symbol, &interner).is_err());
+    }
+}
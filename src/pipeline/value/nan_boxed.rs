@@ -0,0 +1,56 @@
+use crate::pipeline::interner::Symbol;
+
+use super::Unpacked;
+
+// Values that aren't numbers hide in the payload of quiet NaNs that no
+// arithmetic produces: all of QNAN set, plus the sign bit for strings or
+// the low bits for null. Numbers that are NaN are stored as `f64::NAN`,
+// which doesn't have all of QNAN set.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const TAG_NULL: u64 = 1;
+
+/// A value packed into 8 bytes.
+#[derive(Copy, Clone)]
+pub struct RTValue(u64);
+
+impl RTValue {
+    pub fn null() -> Self {
+        Self(QNAN | TAG_NULL)
+    }
+
+    pub fn number(number: f64) -> Self {
+        if number.is_nan() {
+            Self(f64::NAN.to_bits())
+        } else {
+            Self(number.to_bits())
+        }
+    }
+
+    pub fn string(symbol: Symbol) -> Self {
+        Self(SIGN_BIT | QNAN | symbol.index() as u64)
+    }
+
+    #[inline(always)]
+    pub fn unpack(&self) -> Unpacked {
+        if self.0 & QNAN != QNAN {
+            Unpacked::Number(f64::from_bits(self.0))
+        } else if self.0 & SIGN_BIT != 0 {
+            Unpacked::String(Symbol::from_index((self.0 & u32::MAX as u64) as u32))
+        } else {
+            Unpacked::Null
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::RTValue;
+
+    #[test]
+    fn fits_in_eight_bytes() {
+        assert_eq!(mem::size_of::<RTValue>(), 8);
+    }
+}
@@ -0,0 +1,25 @@
+use crate::pipeline::interner::Symbol;
+
+use super::Unpacked;
+
+#[derive(Copy, Clone)]
+pub struct RTValue(Unpacked);
+
+impl RTValue {
+    pub fn null() -> Self {
+        Self(Unpacked::Null)
+    }
+
+    pub fn number(number: f64) -> Self {
+        Self(Unpacked::Number(number))
+    }
+
+    pub fn string(symbol: Symbol) -> Self {
+        Self(Unpacked::String(symbol))
+    }
+
+    #[inline(always)]
+    pub fn unpack(&self) -> Unpacked {
+        self.0
+    }
+}
@@ -1,23 +1,93 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
 use super::{
     bytecode::{
-        self,
-        bytes::{self, FromBytes},
-        Chunk, OpCode,
+        bytes::FromBytes, Chunk, DecodeError, Instruction, OpCode, CONSTANT_LONG_ARG_BYTES,
+        JUMP_ARG_BYTES,
     },
     value::RTValue,
 };
 
+/// The default limit on how many values the stack holds.
 pub const STACK_MAX: usize = 65535;
 
-pub struct VM<'s> {
-    chunk: Option<&'s Chunk<'s>>,
+/// How many backward jumps pass between looks at the clock.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+pub struct VM<'c> {
+    chunk: Option<&'c Chunk>,
+    /// Whether `chunk` passed `Chunk::verify`, so `run` can skip the checks.
+    verified: bool,
     stack: Vec<RTValue>,
+    stack_max: usize,
+    output: Box<dyn Write>,
+    /// The value the last `Return` produced.
+    returned: Option<RTValue>,
+    error_offset: Option<usize>,
+    /// Instructions left to run, if limited.
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    /// Backward jumps left until the clock is checked against `deadline`.
+    deadline_countdown: u32,
+    interrupt: Option<Arc<AtomicBool>>,
 }
 
 #[derive(Debug)]
 pub enum InterpretError {
     CompileError,
     RuntimeError(String),
+    /// Malformed bytecode, such as an instruction at `offset` popping an
+    /// empty stack. The compiler never emits such code.
+    InternalError {
+        offset: usize,
+        message: String,
+    },
+    /// The host stopped the program before it finished.
+    Interrupted(Interrupt),
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretError::CompileError => write!(f, "Compile error"),
+            InterpretError::RuntimeError(message) => write!(f, "{}", message),
+            InterpretError::InternalError { offset, message } => {
+                write!(f, "{:0>4}: {}", offset, message)
+            }
+            InterpretError::Interrupted(interrupt) => write!(f, "{}", interrupt),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+/// Why a program was stopped from outside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// The program ran the number of instructions given to `VM::with_fuel`.
+    OutOfFuel,
+    /// The deadline given to `VM::with_deadline` passed.
+    DeadlineExceeded,
+    /// The flag given to `VM::with_interrupt` was set.
+    Cancelled,
+}
+
+impl Display for Interrupt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupt::OutOfFuel => write!(f, "Out of fuel"),
+            Interrupt::DeadlineExceeded => write!(f, "Deadline exceeded"),
+            Interrupt::Cancelled => write!(f, "Interrupted"),
+        }
+    }
 }
 
 macro_rules! unwrap_or_bail {
@@ -58,80 +128,643 @@ macro_rules! debug_run {
     };
 }
 
-macro_rules! read_constant {
-    ($chunk: expr, $ip: expr, $bytes:expr, $error_msg: expr) => {{
-        let constant_index = unwrap_or_bail!(
-            bytes::next_bytes::<$bytes>($ip),
-            InterpretError::RuntimeError($error_msg.to_string())
-        )
-        .bytes_to_num();
-        *unwrap_or_bail!(
-            $chunk.get_constant(constant_index),
-            InterpretError::RuntimeError(format!("Bad constant index {}", constant_index))
-        )
-    }};
-}
-
-impl<'s> VM<'s> {
+impl<'c> VM<'c> {
     pub fn new() -> Self {
-        let mut stack = Vec::new();
-        stack.reserve(STACK_MAX);
-        Self { chunk: None, stack }
+        let stack = Vec::with_capacity(STACK_MAX);
+        Self {
+            chunk: None,
+            verified: false,
+            stack,
+            stack_max: STACK_MAX,
+            output: Box::new(io::stdout()),
+            returned: None,
+            error_offset: None,
+            fuel: None,
+            deadline: None,
+            deadline_countdown: 0,
+            interrupt: None,
+        }
+    }
+
+    /// Sends what the program prints to `output` instead of stdout.
+    pub fn with_output(self, output: Box<dyn Write>) -> Self {
+        Self { output, ..self }
+    }
+
+    /// Limits the stack to `stack_max` values; pushing more is a "Stack
+    /// overflow" runtime error. Strings are interned when the chunk is
+    /// built and there is no heap yet, so the stack is all a running program
+    /// allocates and this is also its memory limit.
+    pub fn with_stack_max(self, stack_max: usize) -> Self {
+        Self { stack_max, ..self }
+    }
+
+    /// Stops the program once it has run `fuel` instructions.
+    pub fn with_fuel(self, fuel: u64) -> Self {
+        Self {
+            fuel: Some(fuel),
+            ..self
+        }
+    }
+
+    /// Stops the program once `deadline` has passed. The clock is checked
+    /// on backward jumps, since straight-line code ends by itself.
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            deadline_countdown: 0,
+            ..self
+        }
+    }
+
+    /// Stops the program `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Stops the program when another thread, or a signal handler, sets
+    /// `interrupt`. The flag is checked on backward jumps and left set.
+    pub fn with_interrupt(self, interrupt: Arc<AtomicBool>) -> Self {
+        Self {
+            interrupt: Some(interrupt),
+            ..self
+        }
     }
 
-    pub fn with_chunk<'t>(mut self, new_chunk: &'t Chunk<'t>) -> VM<'t> {
+    /// The value the program returned, once it has.
+    pub fn returned(&self) -> Option<RTValue> {
+        self.returned
+    }
+
+    /// Instructions left before the program runs out of fuel, if limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// The values on the stack, bottom first.
+    pub fn stack(&self) -> &[RTValue] {
+        &self.stack
+    }
+
+    pub fn with_chunk<'t>(mut self, new_chunk: &'t Chunk) -> VM<'t> {
         self.stack.clear();
+        self.continue_with(new_chunk)
+    }
+
+    /// Moves on to `new_chunk` without clearing the stack, so a session can
+    /// run chunks one after another on what the earlier ones left there.
+    pub fn continue_with<'t>(self, new_chunk: &'t Chunk) -> VM<'t> {
         VM {
+            returned: None,
+            error_offset: None,
             chunk: Some(new_chunk),
+            verified: new_chunk.verify().is_ok(),
             ..self
         }
     }
 
+    /// Lets go of the chunk, keeping the stack and the settings, so the VM
+    /// can outlive it.
+    pub fn detach(self) -> VM<'static> {
+        VM {
+            chunk: None,
+            verified: false,
+            ..self
+        }
+    }
+
+    /// Clears the stack and what the last run left behind, so the chunk can
+    /// run again from the start.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.returned = None;
+        self.error_offset = None;
+    }
+
+    /// Runs the chunk, on the fast path if it passed `Chunk::verify`.
     pub fn run(&mut self) -> Result<(), InterpretError> {
+        match self.chunk {
+            Some(chunk) if self.verified => {
+                let start = chunk.code().as_ptr();
+                let mut current = start;
+                // SAFETY: `verified` is only set for chunks that verify.
+                let result = unsafe { self.run_verified(chunk, &mut current) };
+                self.error_offset = result.is_err().then(|| current as usize - start as usize);
+                result
+            }
+            _ => self.run_checked(),
+        }
+    }
+
+    /// Runs the chunk, decoding and checking every instruction on the way.
+    /// This is how chunks that don't verify run, and the baseline the fast
+    /// path is measured against.
+    pub fn run_checked(&mut self) -> Result<(), InterpretError> {
+        let mut current = 0;
+        let result = self.checked_loop(&mut current);
+        self.error_offset = result.is_err().then_some(current);
+        result
+    }
+
+    /// Offset of the instruction that stopped the last run with an error.
+    pub fn error_offset(&self) -> Option<usize> {
+        self.error_offset
+    }
+
+    /// The loop behind `run_checked`, keeping the offset of the instruction
+    /// it is on in `current`.
+    fn checked_loop(&mut self, current: &mut usize) -> Result<(), InterpretError> {
         let chunk = unwrap_or_bail!(self.chunk);
-        let mut ip = chunk.code().iter();
+        let mut ip = 0;
         loop {
-            let opcode = unwrap_or_bail!(ip.next());
+            *current = ip;
+            let decoded = unwrap_or_bail!(chunk.instruction_at(ip));
             debug_run!({
                 eprintln!("{:#?}", self.stack);
-                {
-                    let mut ip = ip.clone();
-                    let offset: usize =
-                        opcode as *const u8 as usize - chunk.code().as_ptr() as usize;
-                    chunk.describe_instruction_to_stderr(offset, *opcode, &mut ip);
-                }
+                chunk.describe_instruction_to_stderr(ip);
+            });
+            let instruction = ok_or_bail_with!(decoded, |err: DecodeError| {
+                malformed(ip, err.to_string())
             });
-            let opcode = ok_or_bail_with!(OpCode::try_from(*opcode), |byte| {
-                InterpretError::RuntimeError(format!("Expected OpCode, got byte {}", byte))
+            self.burn_fuel()?;
+            let offset = ip;
+            ip += instruction.length();
+            match instruction {
+                Instruction::Return => {
+                    let value = unwrap_or_bail!(self.stack.pop(), underflow(offset));
+                    return self.finish(value);
+                }
+                Instruction::Constant(constant_index)
+                | Instruction::ConstantLong(constant_index) => {
+                    self.push(*unwrap_or_bail!(
+                        chunk.get_constant(constant_index),
+                        malformed(offset, format!("Bad constant index {}", constant_index))
+                    ))?;
+                }
+                Instruction::Negate => match self.stack.last_mut() {
+                    Some(value) => match value.as_number() {
+                        Some(number) => *value = RTValue::number(-number),
+                        None => return Err(not_a_number()),
+                    },
+                    None => return Err(underflow(offset)),
+                },
+                Instruction::Pop => {
+                    unwrap_or_bail!(self.stack.pop(), underflow(offset));
+                }
+                Instruction::Jump(distance) => ip += distance,
+                Instruction::JumpIfFalse(distance) => {
+                    let condition = unwrap_or_bail!(self.stack.last(), underflow(offset));
+                    if condition.is_falsey() {
+                        ip += distance;
+                    }
+                }
+                Instruction::Loop(distance) => {
+                    self.poll_interrupts()?;
+                    ip = unwrap_or_bail!(
+                        ip.checked_sub(distance),
+                        malformed(
+                            offset,
+                            format!(
+                                "Loop jumps {} bytes before the start of the code",
+                                distance - ip
+                            )
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    /// The dispatch loop for verified chunks. It walks the code with a raw
+    /// instruction pointer and decodes opcodes and operands without bounds
+    /// checks, relying on `Chunk::verify` for what `run_checked` checks as it
+    /// goes: opcodes are valid, operands and constants exist, jumps stay
+    /// within the code and the stack never underflows.
+    ///
+    /// `current` is kept pointing at the instruction being run.
+    ///
+    /// # Safety
+    ///
+    /// `chunk` must pass `Chunk::verify`.
+    #[inline(always)]
+    unsafe fn run_verified(
+        &mut self,
+        chunk: &Chunk,
+        current: &mut *const u8,
+    ) -> Result<(), InterpretError> {
+        let code = chunk.code();
+        let constants = chunk.constants();
+        let start = code.as_ptr();
+        // Jumps may land on the end, which stops the VM like falling off it
+        // does, so `ip` is only moved forward with `wrapping_add`.
+        let end = start.wrapping_add(code.len());
+        let mut ip = start;
+        while ip < end {
+            *current = ip;
+            debug_run!({
+                eprintln!("{:#?}", self.stack);
+                chunk.describe_instruction_to_stderr(unsafe { ip.offset_from(start) } as usize);
             });
-            match opcode {
+            self.burn_fuel()?;
+            let op = unsafe { OpCode::from_u8_unchecked(*ip) };
+            ip = ip.wrapping_add(1);
+            match op {
                 OpCode::Return => {
-                    println!("{:?}", self.stack.pop());
-                    return Ok(());
+                    let value = unsafe { self.stack.pop().unwrap_unchecked() };
+                    return self.finish(value);
                 }
                 OpCode::Constant => {
-                    self.stack.push(read_constant!(
-                        chunk,
-                        &mut ip,
-                        1,
-                        "Missing arg byte for Constant"
-                    ));
+                    let index = unsafe { read_operand::<1>(&mut ip) };
+                    self.push(unsafe { *constants.get_unchecked(index) })?;
                 }
                 OpCode::ConstantLong => {
-                    self.stack.push(read_constant!(
-                        chunk,
-                        &mut ip,
-                        { bytecode::CONSTANT_LONG_ARG_BYTES },
-                        "Missing arg bytes for ConstantLong"
-                    ));
+                    let index = unsafe { read_operand::<CONSTANT_LONG_ARG_BYTES>(&mut ip) };
+                    self.push(unsafe { *constants.get_unchecked(index) })?;
                 }
+                OpCode::Negate => {
+                    let value = unsafe { self.stack.last_mut().unwrap_unchecked() };
+                    match value.as_number() {
+                        Some(number) => *value = RTValue::number(-number),
+                        None => return Err(not_a_number()),
+                    }
+                }
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::Jump => {
+                    let distance = unsafe { read_operand::<JUMP_ARG_BYTES>(&mut ip) };
+                    ip = ip.wrapping_add(distance);
+                }
+                OpCode::JumpIfFalse => {
+                    let distance = unsafe { read_operand::<JUMP_ARG_BYTES>(&mut ip) };
+                    if unsafe { self.stack.last().unwrap_unchecked() }.is_falsey() {
+                        ip = ip.wrapping_add(distance);
+                    }
+                }
+                OpCode::Loop => {
+                    self.poll_interrupts()?;
+                    let distance = unsafe { read_operand::<JUMP_ARG_BYTES>(&mut ip) };
+                    ip = unsafe { ip.sub(distance) };
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Verified code can't underflow the stack, but nothing bounds how deep
+    /// it grows, so both loops push through here.
+    #[inline(always)]
+    fn push(&mut self, value: RTValue) -> Result<(), InterpretError> {
+        if self.stack.len() >= self.stack_max {
+            return Err(InterpretError::RuntimeError("Stack overflow".to_string()));
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn burn_fuel(&mut self) -> Result<(), InterpretError> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(InterpretError::Interrupted(Interrupt::OutOfFuel));
+            }
+            *fuel -= 1;
+        }
+        Ok(())
+    }
+
+    /// Checks the interrupt flag and, every so often, the deadline. Called
+    /// on backward jumps, which every loop that doesn't end by itself takes.
+    #[inline(always)]
+    fn poll_interrupts(&mut self) -> Result<(), InterpretError> {
+        if let Some(interrupt) = &self.interrupt {
+            if interrupt.load(Ordering::Relaxed) {
+                return Err(InterpretError::Interrupted(Interrupt::Cancelled));
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if self.deadline_countdown == 0 {
+                if Instant::now() >= deadline {
+                    return Err(InterpretError::Interrupted(Interrupt::DeadlineExceeded));
+                }
+                self.deadline_countdown = DEADLINE_CHECK_INTERVAL;
             }
+            self.deadline_countdown -= 1;
         }
+        Ok(())
     }
+
+    fn finish(&mut self, value: RTValue) -> Result<(), InterpretError> {
+        self.returned = Some(value);
+        writeln!(self.output, "{:?}", value)
+            .map_err(|err| InterpretError::RuntimeError(err.to_string()))
+    }
+}
+
+fn underflow(offset: usize) -> InterpretError {
+    malformed(offset, "Stack underflow".to_string())
+}
+
+/// An error that only bytecode `Chunk::verify` rejects can cause.
+fn malformed(offset: usize, message: String) -> InterpretError {
+    InterpretError::InternalError { offset, message }
+}
+
+fn not_a_number() -> InterpretError {
+    InterpretError::RuntimeError("Operand must be a number.".to_string())
+}
+
+/// Reads an `N` byte operand at `ip` and moves `ip` past it.
+///
+/// # Safety
+///
+/// The `N` bytes at `ip` must be within the code.
+#[inline(always)]
+unsafe fn read_operand<const N: usize>(ip: &mut *const u8) -> usize
+where
+    [u8; N]: FromBytes<usize>,
+{
+    let bytes = unsafe { ip.cast::<[u8; N]>().read() };
+    *ip = ip.wrapping_add(N);
+    bytes.bytes_to_num()
 }
 
-impl<'s> Default for VM<'s> {
+impl<'c> Default for VM<'c> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{
+        bytecode::{Chunk, Instruction, OpCode},
+        value::RTValue,
+    };
+
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::{InterpretError, Interrupt, STACK_MAX, VM};
+
+    #[test]
+    fn reads_long_constants() {
+        let mut chunk = Chunk::new();
+        for n in 0..70_000 {
+            chunk.push_constant(RTValue::number(n as f64));
+        }
+        chunk.push_load_constant_op(69_999, None);
+        chunk.push_load_constant_op(256, None);
+        chunk.push_load_constant_op(7, None);
+        chunk.push_op_code(OpCode::Return, None);
+        let mut vm = VM::new().with_chunk(&chunk);
+        vm.run().unwrap();
+        let stack: Vec<f64> = vm
+            .stack
+            .iter()
+            .map(|value| match value.as_number() {
+                Some(n) => n,
+                None => panic!("Unexpected {:?}", value),
+            })
+            .collect();
+        assert_eq!(stack, vec![69_999.0, 256.0]);
+    }
+
+    #[test]
+    fn jumps() {
+        // The first pass replaces the nil condition with a number, so the
+        // second pass jumps out of the loop.
+        let mut chunk = Chunk::new();
+        let condition = chunk.push_constant(RTValue::null());
+        let done = chunk.push_constant(RTValue::number(1.0));
+        chunk.push_load_constant_op(condition, None);
+        let loop_start = chunk.code().len();
+        let exit = chunk.push_jump(OpCode::JumpIfFalse, None);
+        let skip = chunk.push_jump(OpCode::Jump, None);
+        chunk.patch_jump(exit);
+        chunk.push_op_code(OpCode::Pop, None);
+        chunk.push_load_constant_op(done, None);
+        chunk.push_loop(loop_start, None);
+        chunk.patch_jump(skip);
+        chunk.push_op_code(OpCode::Return, None);
+        chunk.verify().unwrap();
+        let mut vm = VM::new().with_chunk(&chunk);
+        vm.run().unwrap();
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn fast_path_matches_checked_loop() {
+        let listings = [
+            "Constant 1\nNegate\nConstant nil\nConstant \"a\"\nPop\nReturn",
+            "Constant nil\nJumpIfFalse skip\nNegate\nskip:\nConstantLong 0",
+            "Constant 3\nJumpIfFalse skip\nNegate\nskip:\nConstantLong 0",
+            "Constant nil\nNegate\nReturn",
+            "Constant 2\ntop:\nJumpIfFalse end\nPop\nConstant nil\nLoop top\nend:\nReturn",
+        ];
+        for listing in listings {
+            let chunk = Chunk::assemble(listing, "test").unwrap();
+            let mut fast = VM::new()
+                .with_output(Box::new(io::sink()))
+                .with_chunk(&chunk);
+            let mut checked = VM::new()
+                .with_output(Box::new(io::sink()))
+                .with_chunk(&chunk);
+            assert!(fast.verified, "{}", listing);
+            let fast_result = fast.run().map_err(|err| format!("{:?}", err));
+            let checked_result = checked.run_checked().map_err(|err| format!("{:?}", err));
+            assert_eq!(fast_result, checked_result, "{}", listing);
+            assert_eq!(format!("{:?}", fast.stack), format!("{:?}", checked.stack));
+        }
+    }
+
+    fn run_listing(listing: &str, stack_max: usize) -> Result<(), InterpretError> {
+        let chunk = Chunk::assemble(listing, "test").unwrap();
+        VM::new()
+            .with_output(Box::new(io::sink()))
+            .with_stack_max(stack_max)
+            .with_chunk(&chunk)
+            .run()
+    }
+
+    #[test]
+    fn detects_stack_overflow() {
+        let overflow = |result| matches!(result, Err(InterpretError::RuntimeError(message)) if message == "Stack overflow");
+        // Verified straight-line code on the fast path.
+        let listing = "Constant 1\n".repeat(4) + "Return";
+        assert!(run_listing(&listing, 4).is_ok());
+        assert!(overflow(run_listing(&listing, 3)));
+        // A loop that pushes forever doesn't verify, so it runs checked.
+        assert!(overflow(run_listing("top:\nConstant 1\nLoop top", 16)));
+    }
+
+    #[test]
+    fn reports_underflow_offsets() {
+        for (listing, expected) in [
+            ("Return", 0),
+            ("Constant 1\nPop\nPop", 3),
+            ("Constant 1\nPop\nNegate", 3),
+            ("Constant 1\nConstant 2\nPop\nPop\nJumpIfFalse end\nend:", 6),
+        ] {
+            match run_listing(listing, STACK_MAX) {
+                Err(InterpretError::InternalError { offset, message }) => {
+                    assert_eq!((offset, message.as_str()), (expected, "Stack underflow"))
+                }
+                other => panic!("{}: {:?}", listing, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_malformed_bytecode() {
+        let error = |build: fn(&mut Chunk)| {
+            let mut chunk = Chunk::new();
+            build(&mut chunk);
+            match VM::new().with_chunk(&chunk).run() {
+                Err(InterpretError::InternalError { offset, message }) => (offset, message),
+                other => panic!("{:?}", other),
+            }
+        };
+        assert_eq!(
+            error(|chunk| chunk.push_op_arg(200, None)),
+            (0, "Unknown op 200".to_string())
+        );
+        assert_eq!(
+            error(|chunk| {
+                chunk.push_constant_and_load_op(RTValue::null(), None);
+                chunk.push_op_code(OpCode::ConstantLong, None);
+                chunk.push_op_arg(0, None);
+            }),
+            (
+                2,
+                "ConstantLong expects 3 operand bytes, found 1".to_string()
+            )
+        );
+        assert_eq!(
+            error(|chunk| chunk.push_load_constant_op(3, None)),
+            (0, "Bad constant index 3".to_string())
+        );
+        assert_eq!(
+            error(|chunk| {
+                chunk.push_constant_and_load_op(RTValue::null(), None);
+                chunk.push_op_code(OpCode::Pop, None);
+                chunk.push_instruction(Instruction::Loop(10), None);
+            }),
+            (
+                3,
+                "Loop jumps 4 bytes before the start of the code".to_string()
+            )
+        );
+    }
+
+    /// Runs a loop that never ends on both dispatch loops of VMs set up by
+    /// `configure`, and returns how each stopped.
+    fn run_forever(configure: impl Fn(VM<'static>) -> VM<'static>) -> [InterpretError; 2] {
+        let chunk = Chunk::assemble("top:\nLoop top", "test").unwrap();
+        let fast = configure(VM::new()).with_chunk(&chunk).run();
+        let checked = configure(VM::new()).with_chunk(&chunk).run_checked();
+        [fast.unwrap_err(), checked.unwrap_err()]
+    }
+
+    #[test]
+    fn stops_when_out_of_fuel() {
+        for err in run_forever(|vm| vm.with_fuel(100)) {
+            assert!(matches!(
+                err,
+                InterpretError::Interrupted(Interrupt::OutOfFuel)
+            ));
+        }
+
+        let chunk = Chunk::assemble("Constant 1\nPop\nConstant 2\nReturn", "test").unwrap();
+        let mut vm = VM::new()
+            .with_output(Box::new(io::sink()))
+            .with_fuel(4)
+            .with_chunk(&chunk);
+        vm.run().unwrap();
+        assert_eq!(vm.fuel(), Some(0));
+        let mut vm = VM::new().with_fuel(3).with_chunk(&chunk);
+        assert!(matches!(
+            vm.run(),
+            Err(InterpretError::Interrupted(Interrupt::OutOfFuel))
+        ));
+    }
+
+    #[test]
+    fn stops_at_deadline() {
+        for err in run_forever(|vm| vm.with_timeout(Duration::from_millis(10))) {
+            assert!(matches!(
+                err,
+                InterpretError::Interrupted(Interrupt::DeadlineExceeded)
+            ));
+        }
+    }
+
+    #[test]
+    fn stops_when_interrupted() {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let setter = {
+            let interrupt = interrupt.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                interrupt.store(true, Ordering::Relaxed);
+            })
+        };
+        for err in run_forever(|vm| vm.with_interrupt(interrupt.clone())) {
+            assert!(matches!(
+                err,
+                InterpretError::Interrupted(Interrupt::Cancelled)
+            ));
+        }
+        setter.join().unwrap();
+    }
+
+    #[test]
+    fn records_error_offsets() {
+        let chunk = Chunk::assemble("Constant 1\nNegate\nConstant nil\nNegate", "test").unwrap();
+        let mut vm = VM::new().with_chunk(&chunk);
+        assert!(vm.run().is_err());
+        assert_eq!(vm.error_offset(), Some(5));
+        assert!(vm.run_checked().is_err());
+        assert_eq!(vm.error_offset(), Some(5));
+
+        let chunk = Chunk::assemble("Constant 1\nPop\nPop", "test").unwrap();
+        let mut vm = VM::new().with_chunk(&chunk);
+        assert!(vm.run().is_err());
+        assert_eq!(vm.error_offset(), Some(3));
+    }
+
+    #[test]
+    fn runs_unverified_chunks_checked() {
+        let chunk = Chunk::assemble("Pop\nNegate", "test").unwrap();
+        let mut vm = VM::new().with_chunk(&chunk);
+        assert!(!vm.verified);
+        assert!(matches!(
+            vm.run(),
+            Err(InterpretError::InternalError { offset: 0, message }) if message == "Stack underflow"
+        ));
+    }
+
+    #[test]
+    fn continues_on_the_stack_earlier_chunks_left() {
+        let first = Chunk::assemble("Constant 2\nConstant 3", "test").unwrap();
+        let mut vm = VM::new().with_output(Box::new(io::sink())).with_chunk(&first);
+        vm.run().unwrap();
+        let vm = vm.detach();
+        assert_eq!(vm.stack().len(), 2);
+
+        let second = Chunk::assemble("Negate\nReturn", "test").unwrap();
+        let mut vm = vm.continue_with(&second);
+        vm.run().unwrap();
+        assert_eq!(vm.returned().and_then(|value| value.as_number()), Some(-3.0));
+        assert_eq!(vm.stack().len(), 1);
+        let vm = vm.with_chunk(&second);
+        assert!(vm.stack().is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+//! The interactive prompt, which `repl --listings` starts. Until there is a
+//! compiler, entries are assembler listings (see `bytecode::assembler`),
+//! not Lox source; keeping globals between entries and printing the value
+//! of expression statements wait on it too.
+//!
+//! An entry whose last line is a label is continued on the next line. All
+//! entries run on one VM, so values an entry leaves on the stack are there
+//! for the next, and a returned value is printed. One interner serves the
+//! whole session. Errors are reported and the session goes on.
+//!
+//! Lines starting with `:` are commands for looking at the pipeline; `:help`
+//! lists them.
+
+use std::{
+    io::{self, BufRead, Write},
+    time::Instant,
+};
+
+mod editor;
+pub use editor::Editor;
+
+use crate::{
+    interpreter::{self, Interpreter},
+    pipeline::{
+        bytecode::Chunk, interner::Interner, scanner, source::Source, tokens::TokenInfo, vm::VM,
+    },
+};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+const HELP: &str = "\
+:tokens <code>   show the tokens code scans to
+:ast <code>      show the parse tree (needs the parser)
+:disasm [code]   show the bytecode code assembles to, or the last entry's
+:globals         list global variables (needs the compiler)
+:stack           show the values on the VM stack
+:load <file>     run a chunk file or listing in the session
+:reset           start the session over
+:time <code>     run code and show how long it took
+:help            show this list";
+
+/// What reading a line of input gave.
+pub enum ReadLine {
+    Line(String),
+    /// The user pressed Ctrl-C.
+    Interrupted,
+    Eof,
+}
+
+/// Where the REPL's lines come from.
+pub trait Input {
+    /// Shows `prompt` and reads a line, without its line ending.
+    fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<ReadLine>;
+
+    /// Remembers a submitted entry for recalling later.
+    fn add_history(&mut self, _entry: &str) {}
+
+    /// Follows the session to a new interner after `:reset`, for input that
+    /// completes names from it.
+    fn set_interner(&mut self, _interner: &Interner) {}
+}
+
+/// Lines from a reader, for input that isn't a terminal.
+pub struct Lines<R>(pub R);
+
+impl<R: BufRead> Input for Lines<R> {
+    fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<ReadLine> {
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        let mut line = String::new();
+        if self.0.read_line(&mut line)? == 0 {
+            return Ok(ReadLine::Eof);
+        }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(ReadLine::Line(line))
+    }
+}
+
+#[derive(Default)]
+pub struct Repl {
+    interpreter: Interpreter,
+    /// The session's VM, between entries. It is made on the first one.
+    vm: Option<VM<'static>>,
+    /// The chunk the last entry assembled to, for `:disasm`.
+    last: Option<Chunk>,
+    entries: usize,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The session's interner, for completing the names it has seen.
+    pub fn interner(&self) -> &Interner {
+        self.interpreter.interner()
+    }
+
+    /// Reads entries from `input` until it ends. A blank line submits an
+    /// unfinished entry as it is, so a mistake can't trap the prompt, and
+    /// Ctrl-C throws it away.
+    pub fn run(&mut self, input: &mut impl Input, mut output: impl Write) -> io::Result<()> {
+        let mut entry = String::new();
+        loop {
+            let prompt = if entry.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            let line = match input.read_line(prompt, &mut output)? {
+                ReadLine::Line(line) => line,
+                ReadLine::Interrupted => {
+                    entry.clear();
+                    continue;
+                }
+                ReadLine::Eof => break,
+            };
+            let blank = line.trim().is_empty();
+            if blank && entry.is_empty() {
+                continue;
+            }
+            if entry.is_empty() && line.starts_with(':') {
+                input.add_history(&line);
+                let interner = self.interner().clone();
+                self.command(&line, &mut output)?;
+                if !self.interner().shares_table_with(&interner) {
+                    input.set_interner(self.interner());
+                }
+                continue;
+            }
+            entry.push_str(&line);
+            entry.push('\n');
+            if !blank && is_incomplete(&entry) {
+                continue;
+            }
+            input.add_history(&entry);
+            self.eval(&entry, &mut output)?;
+            entry.clear();
+        }
+        writeln!(output)
+    }
+
+    /// Assembles `entry` and runs it on the session's VM, printing the value
+    /// it returns.
+    pub fn eval(&mut self, entry: &str, output: &mut impl Write) -> io::Result<()> {
+        self.entries += 1;
+        let source_name = format!("<repl {}>", self.entries);
+        self.run_program(entry.as_bytes(), &source_name, output)
+    }
+
+    /// Loads `program`, a chunk file or a listing, and runs it on the
+    /// session's VM.
+    fn run_program(
+        &mut self,
+        program: &[u8],
+        source_name: &str,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        let chunk = match self.interpreter.load(program, source_name) {
+            Ok(chunk) => chunk,
+            Err(err) => return writeln!(output, "Error: {}", err),
+        };
+        let vm = self
+            .vm
+            .take()
+            .unwrap_or_else(|| VM::new().with_output(Box::new(io::sink())));
+        let mut vm = vm.continue_with(&chunk);
+        let result = vm.run();
+        let returned = vm.returned();
+        let error = result
+            .err()
+            .map(|err| interpreter::Error::at(err, &vm, &chunk));
+        if error.is_some() {
+            // Whatever a failed entry left on the stack is of no use.
+            vm.reset();
+        }
+        self.vm = Some(vm.detach());
+        self.last = Some(chunk);
+        match (error, returned) {
+            (Some(err), _) => writeln!(output, "Error: {}", err),
+            (None, Some(value)) => writeln!(output, "{}", self.interpreter.format(value)),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Runs the `:` command on `line`.
+    pub fn command(&mut self, line: &str, output: &mut impl Write) -> io::Result<()> {
+        let (name, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        match name {
+            ":tokens" => {
+                let source = Source::new("<tokens>".to_string(), argument.to_string());
+                self.show_tokens(source, output)
+            }
+            ":ast" => writeln!(output, ":ast needs the parser, which doesn't exist yet"),
+            ":disasm" if argument.is_empty() => match &self.last {
+                Some(chunk) => {
+                    chunk.describe(output);
+                    Ok(())
+                }
+                None => writeln!(output, "No entry to disassemble yet"),
+            },
+            ":disasm" => {
+                match self.interpreter.load(argument.as_bytes(), "<disasm>") {
+                    Ok(chunk) => chunk.describe(output),
+                    Err(err) => writeln!(output, "Error: {}", err)?,
+                }
+                Ok(())
+            }
+            ":globals" => writeln!(
+                output,
+                ":globals needs the compiler, which doesn't exist yet"
+            ),
+            ":stack" => {
+                let stack = self.vm.as_ref().map_or(&[][..], |vm| vm.stack());
+                let values: Vec<String> = stack
+                    .iter()
+                    .map(|value| self.interpreter.format(*value))
+                    .collect();
+                writeln!(output, "[{}]", values.join(", "))
+            }
+            ":load" if argument.is_empty() => writeln!(output, ":load needs a file"),
+            ":load" => match std::fs::read(argument) {
+                Ok(program) => self.run_program(&program, argument, output),
+                Err(err) => writeln!(output, "Failed to read {}: {}", argument, err),
+            },
+            ":reset" => {
+                // A new interner, too, so names from before are forgotten.
+                *self = Self::new();
+                writeln!(output, "Session reset")
+            }
+            ":time" => {
+                let start = Instant::now();
+                self.eval(argument, output)?;
+                writeln!(output, "Took {:.3?}", start.elapsed())
+            }
+            ":help" => writeln!(output, "{}", HELP),
+            _ => writeln!(output, "Unknown command {}; :help lists commands", name),
+        }
+    }
+
+    fn show_tokens(&mut self, source: Source, output: &mut impl Write) -> io::Result<()> {
+        let (errors, tokens) = scanner::scan(source, self.interpreter.interner());
+        for error in &errors {
+            writeln!(output, "Error: {}", error.message())?;
+        }
+        if errors.is_empty() {
+            for token in tokens
+                .iter()
+                .filter(|token| token.info() != &TokenInfo::EOF)
+            {
+                writeln!(output, "{:?}", token)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `entry` needs more lines: its last line is a label, which names
+/// an instruction still to come.
+pub fn is_incomplete(entry: &str) -> bool {
+    entry.lines().next_back().is_some_and(|line| {
+        // Labels can't hold a `;`, so whatever follows one is a comment.
+        let code = line.split(';').next().unwrap_or_default().trim();
+        code.strip_suffix(':').is_some_and(|label| {
+            !label.is_empty() && label.chars().all(|c| c == '_' || c.is_alphanumeric())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use crate::pipeline::interner::Interner;
+
+    use super::{is_incomplete, Input, Lines, ReadLine, Repl};
+
+    #[test]
+    fn detects_incomplete_entries() {
+        for entry in ["start:", "Constant 1\nloop:\n", "  end: ; the end\n"] {
+            assert!(is_incomplete(entry), "{:?}", entry);
+        }
+        for entry in [
+            "",
+            "Constant 1",
+            "start:\nReturn\n",
+            "Constant \"a:\"",
+            "Constant 1 ; at:",
+            ":",
+            "print x:",
+        ] {
+            assert!(!is_incomplete(entry), "{:?}", entry);
+        }
+    }
+
+    fn session(input: &str) -> String {
+        let mut output = vec![];
+        Repl::new()
+            .run(&mut Lines(input.as_bytes()), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn runs_entries_on_one_vm_and_survives_errors() {
+        let output = session(
+            "start:\nConstant 1\n\nConstant 2\nNegate\nReturn\n\
+             Bogus\nReturn\nConstant nil\nNegate\nReturn\n",
+        );
+        assert_eq!(
+            output,
+            "> ... > > > > -2\n\
+             > Error: line 1: Unknown mnemonic Bogus\n\
+             > 1\n\
+             > > Error: Operand must be a number.\n\
+             > Error: 0000: Stack underflow\n\
+             > \n"
+        );
+    }
+
+    #[test]
+    fn runs_commands() {
+        let path = std::env::temp_dir().join(format!("repl_load_{}.lox", std::process::id()));
+        std::fs::write(&path, "Constant \"loaded\"\nConstant 2\n").unwrap();
+        let output = session(&format!(
+            ":tokens nil\n:ast 1\n:globals\n:disasm\n:load {}\n:stack\n:disasm\n\
+             :disasm Constant 7\nReturn\n:reset\n:stack\n:tokens x\n:nope\nstart:\n:tokens\n",
+            path.display()
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            output,
+            "> Token<0,3,Nil>(nil)\n\
+             > :ast needs the parser, which doesn't exist yet\n\
+             > :globals needs the compiler, which doesn't exist yet\n\
+             > No entry to disassemble yet\n\
+             > > [loaded, 2]\n\
+             > 0000    ? Constant    0 String(\"loaded\")\n\
+             0002    ? Constant    1 Number(2.0)\n\
+             > 0000    ? Constant    0 Number(7.0)\n\
+             > 2\n\
+             > Session reset\n\
+             > []\n\
+             > Token<0,1,Identifier(Symbol(0))>(x)\n\
+             > Unknown command :nope; :help lists commands\n\
+             > ... Error: line 2: Unknown mnemonic :tokens\n\
+             > \n"
+        );
+
+        let output = session(":time Constant 1\nReturn\n");
+        assert!(output.starts_with("> Took "));
+        assert!(output.ends_with("\n> 1\n> \n"));
+    }
+
+    #[test]
+    fn blank_lines_submit_unfinished_entries() {
+        // `:stack` is only a command at the start of an entry.
+        let output = session("start:\n\n:stack\n");
+        assert_eq!(output, "> ... > []\n> \n");
+    }
+
+    /// Replays lines, with `None` for Ctrl-C, and keeps the history and the
+    /// interner it was last given.
+    #[derive(Default)]
+    struct Scripted {
+        lines: Vec<Option<&'static str>>,
+        history: Vec<String>,
+        interner: Option<Interner>,
+    }
+
+    impl Input for Scripted {
+        fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<ReadLine> {
+            write!(output, "{}", prompt)?;
+            if self.lines.is_empty() {
+                return Ok(ReadLine::Eof);
+            }
+            Ok(match self.lines.remove(0) {
+                Some(line) => ReadLine::Line(line.to_string()),
+                None => ReadLine::Interrupted,
+            })
+        }
+
+        fn add_history(&mut self, entry: &str) {
+            self.history.push(entry.to_string());
+        }
+
+        fn set_interner(&mut self, interner: &Interner) {
+            self.interner = Some(interner.clone());
+        }
+    }
+
+    #[test]
+    fn ctrl_c_abandons_entries() {
+        let mut input = Scripted {
+            lines: vec![Some("start:"), Some("end:"), None, Some("Return")],
+            ..Scripted::default()
+        };
+        let mut output = vec![];
+        Repl::new().run(&mut input, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> ... ... > Error: 0000: Stack underflow\n> \n"
+        );
+        assert_eq!(input.history, ["Return\n"]);
+    }
+
+    #[test]
+    fn reset_starts_a_new_interner() {
+        let mut input = Scripted {
+            lines: vec![
+                Some("Constant \"old\""),
+                Some(":stack"),
+                Some(":reset"),
+                Some(":tokens new"),
+            ],
+            ..Scripted::default()
+        };
+        let mut repl = Repl::new();
+        let old = repl.interner().clone();
+        let mut output = vec![];
+        repl.run(&mut input, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> > [old]\n> Session reset\n\
+             > Token<0,3,Identifier(Symbol(0))>(new)\n> \n"
+        );
+        assert!(!repl.interner().shares_table_with(&old));
+        assert_eq!(old.strings().len(), 1);
+        let interner = input.interner.expect("the input follows the reset");
+        assert!(interner.shares_table_with(repl.interner()));
+        assert_eq!(interner.strings(), repl.interner().strings());
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Helper,
+};
+
+use crate::pipeline::{interner::Interner, tokens::KEYWORDS};
+
+use super::{Input, ReadLine};
+
+const HISTORY_FILE: &str = ".lox_history";
+
+/// Terminal input with line editing, history kept in `~/.lox_history` and
+/// Tab completion.
+pub struct Editor {
+    editor: rustyline::Editor<Completions, DefaultHistory>,
+    history: Option<PathBuf>,
+}
+
+impl Editor {
+    /// Completes names from `interner`, which should be the session's.
+    pub fn new(interner: &Interner) -> rustyline::Result<Self> {
+        let mut editor = rustyline::Editor::new()?;
+        editor.set_helper(Some(Completions {
+            interner: interner.clone(),
+        }));
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(path) = &history {
+            // A missing or unreadable history file just means no history.
+            let _ = editor.load_history(path);
+        }
+        Ok(Self { editor, history })
+    }
+}
+
+impl Input for Editor {
+    fn read_line(&mut self, prompt: &str, _: &mut dyn Write) -> io::Result<ReadLine> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(ReadLine::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(ReadLine::Interrupted),
+            Err(ReadlineError::Eof) => Ok(ReadLine::Eof),
+            Err(ReadlineError::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn add_history(&mut self, entry: &str) {
+        let _ = self.editor.add_history_entry(entry.trim_end());
+        if let Some(path) = &self.history {
+            let _ = self.editor.save_history(path);
+        }
+    }
+
+    fn set_interner(&mut self, interner: &Interner) {
+        if let Some(completions) = self.editor.helper_mut() {
+            completions.interner = interner.clone();
+        }
+    }
+}
+
+/// Completes keywords and the names the session has used.
+struct Completions {
+    interner: Interner,
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&self.interner, line, pos))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
+
+/// The start of the word ending at `pos` and the keywords and identifiers
+/// it could be completed to, sorted.
+pub(super) fn complete(interner: &Interner, line: &str, pos: usize) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| unicode_ident::is_xid_continue(*c))
+        .last()
+        .map_or(pos, |(index, _)| index);
+    let prefix = &line[start..pos];
+    if prefix.is_empty() {
+        return (pos, vec![]);
+    }
+    let mut candidates: Vec<String> = KEYWORDS
+        .iter()
+        .map(|(keyword, _)| keyword.to_string())
+        .chain(
+            interner
+                .strings()
+                .iter()
+                .filter(|name| is_identifier(name))
+                .map(|name| name.to_string()),
+        )
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || unicode_ident::is_xid_start(c))
+        && chars.all(unicode_ident::is_xid_continue)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::interner::Interner;
+
+    use super::complete;
+
+    #[test]
+    fn completes_keywords_and_names() {
+        let interner = Interner::new();
+        interner.intern("value");
+        interner.intern("var_count");
+        interner.intern("not a name");
+        assert_eq!(
+            complete(&interner, "print va", 8),
+            (
+                6,
+                vec![
+                    "value".to_string(),
+                    "var".to_string(),
+                    "var_count".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            complete(&interner, "whi(x)", 3),
+            (0, vec!["while".to_string()])
+        );
+        assert_eq!(complete(&interner, "x = ", 4), (4, vec![]));
+        assert_eq!(complete(&interner, "n", 1), (0, vec!["nil".to_string()]));
+    }
+}